@@ -1,10 +1,10 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
 use core::time::Duration;
-use std::{io::{BufWriter, Write}, time::Instant};
+use std::{io::BufWriter, path::Path, time::Instant};
 
-use glam::{Quat, Vec3};
-use nvm_v3d::{meshing::{VisFace, mesh_chunk_plane, decode_vertex, create_quad_for_vertex}, tiles::TileIdentifier, world::{ChunkStorage, PosBlock}};
+use glam::{Affine3A, Quat, Vec3};
+use nvm_v3d::{export::{ExportMesh, ExportNode, write_glb, write_obj, write_ply}, meshing::{ChunkMesh, ChunkMeshMode}, tiles::{TileDefinition, TileIdentifier, TileRegistry}, world::{ChunkStorage, PosChunk, CHUNK_LENGTH}};
 
 fn main() {
 
@@ -12,17 +12,19 @@ fn main() {
 
     let args: Vec<_> = std::env::args().skip(1).collect();
 
-    let chunk = read_obj(&args[0]);
+    let mut registry = TileRegistry::new();
+    let tile_solid = registry.register("example:solid", TileDefinition::new_opaque("Solid")).unwrap();
+    let mode = if args.get(2).is_some_and(|v| v == "greedy") { ChunkMeshMode::Greedy } else { ChunkMeshMode::Culled };
+
+    let chunk = read_obj(&args[0], tile_solid);
     let mut storage = ChunkStorage::new_empty();
-    let mut verts = vec![0; 16*16*16*6];
-    let mut vert_count = 0;
+    let mut mesh = ChunkMesh::new(mode);
     let mut vis_time_total = Duration::ZERO;
     let mut mesh_time_total = Duration::ZERO;
     for i in 0..LOOP_COUNT { 
-        let (vis_time, mesh_time, vert_count_iter) = do_loop(&chunk, &mut verts, &mut storage); 
+        let (vis_time, mesh_time) = do_loop(&chunk, &mut mesh, &mut storage, &registry); 
         vis_time_total  += vis_time;
         mesh_time_total += mesh_time;
-        vert_count = vert_count_iter;
         if i % 10_000 == 0 {
             println!("Loop {i}");
         }
@@ -40,18 +42,19 @@ fn main() {
     println!("Processing took: {proc_time_avg:.2}us/iter ({:.0} iter/sec) [{:.0}ms total]", 1e6/proc_time_avg, (vis_time_total.as_millis() + mesh_time_total.as_millis()) as f64);
     println!("-----------------------");
 
-    let ((), write_time) = do_time(|| write_obj(&args[1], &verts[..vert_count]));
+    let ((), write_time) = do_time(|| write_mesh(&args[1], &mesh));
     println!("Writing took: {}us", write_time.as_micros());
 }
 
 fn do_loop(
-    chunk:   &[bool],
-    verts:   &mut [u32], 
-    storage: &mut ChunkStorage
-) -> (Duration, Duration, usize) {
-    let ((), vis_time) = do_time(|| build_vis(chunk, storage));
-    let (vert_count, mesh_time) = do_time(|| process_chunk(verts, storage));
-    (vis_time, mesh_time, vert_count)
+    chunk:    &[TileIdentifier; CHUNK_LENGTH],
+    mesh:     &mut ChunkMesh, 
+    storage:  &mut ChunkStorage,
+    registry: &TileRegistry,
+) -> (Duration, Duration) {
+    let ((), vis_time) = do_time(|| storage.set_identifiers(chunk, registry));
+    let ((), mesh_time) = do_time(|| mesh.build(storage));
+    (vis_time, mesh_time)
 }
 
 fn do_time<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
@@ -61,64 +64,27 @@ fn do_time<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
     (result, end.duration_since(start))
 }
 
-fn build_vis(chunk: &[bool], storage: &mut ChunkStorage) {
-    // Set chunk 1,1,1 to the vis mask, extend outside 1 block for culling
-    for z in 0..32 {
-        for y in 0..32 {
-            for x in 0..32 {
-                if !chunk[x + y*32 + z*32*32] {
-                    continue;
-                }
-                storage.update(
-                    PosBlock::new(x as i16, y as i16, z as i16), 
-                    TileIdentifier::DEFAULT, 
-                    true
-                );
-            }
-        }
-    }
-}
-
-fn process_chunk(result: &mut [u32], storage: &ChunkStorage) -> usize {
-    let mut size = 0;
-    for x in 0..4 {
-        for y in 0..4 {
-            for z in 0..4 {
-                let offset = [x, y, z];
-                for i in 0..8 {
-                    size += mesh_chunk_plane(storage, offset, VisFace::PosX, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::PosY, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::PosZ, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::NegX, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::NegY, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::NegZ, i, &mut result[size..]);
-                }
-                
-            }
-        }
-    }
-    size
-}
+/// Writes the mesh as OBJ, PLY or glTF binary depending on the extension,
+/// rotated from the world's Z-up to Y-up.
+fn write_mesh(path: &str, chunk_mesh: &ChunkMesh) {
+    let mut mesh = ExportMesh::default();
+    mesh.add_chunk_mesh(chunk_mesh, |_| ExportMesh::WHITE).unwrap();
+    mesh.transform(Affine3A::from_quat(Quat::from_axis_angle(Vec3::X, (-90.0_f32).to_radians())));
 
-fn write_obj(path: &str, verts: &[u32]) {
     let mut file = BufWriter::new(std::fs::File::create(path).unwrap());
-
-    let rot = Quat::from_axis_angle(Vec3::X, (-90.0_f32).to_radians());
-    for (i, (x, y, z, face)) in verts.iter().enumerate().map(|(i, v)| (i, decode_vertex(*v))) {
-        create_quad_for_vertex(x, y, z, face).map(|vert| rot.mul_vec3(vert.as_vec3()).round().as_ivec3()).map(|vert| {
-            writeln!(&mut file, "v {} {} {}", vert.x, vert.y, vert.z).unwrap();
-        });
-        writeln!(&mut file, "f {} {} {}", i*6+1, i*6+2, i*6+3).unwrap();
-        writeln!(&mut file, "f {} {} {}", i*6+4, i*6+5, i*6+6).unwrap();
-    }
+    match Path::new(path).extension().and_then(|v| v.to_str()) {
+        Some("ply") => write_ply(&mut file, &mesh),
+        Some("glb") => write_glb(&mut file, &[ExportNode::for_chunk(PosChunk::new(0, 0, 0), &mesh)]),
+        _           => write_obj(&mut file, &mesh),
+    }.unwrap();
 }
 
-fn read_obj(path: &str) -> Vec<bool> {
+fn read_obj(path: &str, tile: TileIdentifier) -> Box<[TileIdentifier; CHUNK_LENGTH]> {
     let data = &dot_vox::load(path).unwrap().models[0].voxels;
-    let mut chunk = vec![false; 32*32*32];
+    let mut chunk = vec![TileIdentifier::DEFAULT; CHUNK_LENGTH];
     for entry in data {
         if entry.x >= 32 || entry.y >= 32 || entry.z >= 32 { continue; }
-        chunk[(entry.x as usize) + (entry.y as usize)*32 + (entry.z as usize)*32*32] = true;
+        chunk[(entry.x as usize) + (entry.y as usize)*32 + (entry.z as usize)*32*32] = tile;
     }
-    chunk
+    chunk.into_boxed_slice().try_into().unwrap()
 }
\ No newline at end of file
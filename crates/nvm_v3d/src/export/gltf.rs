@@ -0,0 +1,203 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use core::fmt::Write as _;
+use std::io::{self, Write};
+
+use glam::Vec3;
+
+use crate::world::{PosBlock, PosChunk, PosWorld};
+
+use super::ExportMesh;
+
+const GLB_MAGIC:      u32 = 0x4654_6C67; // "glTF"
+const GLB_VERSION:    u32 = 2;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A; // "JSON"
+const GLB_CHUNK_BIN:  u32 = 0x004E_4942; // "BIN\0"
+
+const GL_ARRAY_BUFFER:         u32 = 34962;
+const GL_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GL_UNSIGNED_BYTE:        u32 = 5121;
+const GL_UNSIGNED_INT:         u32 = 5125;
+const GL_FLOAT:                u32 = 5126;
+
+/// A mesh placed in the scene written by [`write_glb`].
+#[derive(Debug, Clone)]
+pub struct ExportNode<'a> {
+    pub name:        String,
+    pub translation: Vec3,
+    pub mesh:        &'a ExportMesh,
+}
+
+impl<'a> ExportNode<'a> {
+
+    #[must_use]
+    pub fn new(name: impl Into<String>, translation: Vec3, mesh: &'a ExportMesh) -> Self {
+        Self{ name: name.into(), translation, mesh }
+    }
+
+    /// A node for a chunk-local mesh, placed at the chunk's world position.
+    #[must_use]
+    pub fn for_chunk(pos: PosChunk, mesh: &'a ExportMesh) -> Self {
+        let origin = PosWorld::from_chunk_and_block(pos, PosBlock::new(0, 0, 0));
+        Self::new(format!("chunk_{}_{}_{}", pos.x, pos.y, pos.z), origin.as_ivec3().as_vec3(), mesh)
+    }
+
+}
+
+/// Writes the nodes as a binary glTF 2.0 scene, with one node and mesh per
+/// [`ExportNode`] sharing a single buffer.
+///
+/// Positions and translations are written as they are, glTF being Y-up
+/// they may need [`ExportMesh::transform`] first.
+pub fn write_glb(dest: &mut impl Write, nodes: &[ExportNode<'_>]) -> io::Result<()> {
+    let mut bin       = Vec::new();
+    let mut meshes    = Vec::new();
+    let mut accessors = Vec::new();
+    let mut views     = Vec::new();
+    let mut json_nodes = Vec::new();
+
+    for node in nodes {
+        let mut json_node = format!(
+            r#"{{"name":"{}","translation":[{},{},{}]"#,
+            escape_json(&node.name), node.translation.x, node.translation.y, node.translation.z,
+        );
+
+        // Accessors can't be empty, so empty meshes become plain nodes
+        if let Some((min, max)) = node.mesh.bounds().filter(|_| !node.mesh.is_empty()) {
+            let mesh  = node.mesh;
+            let count = mesh.positions().len();
+            let first = accessors.len();
+
+            views.push(push_view(&mut bin, mesh.positions().iter().flatten().flat_map(|v| v.to_le_bytes()), GL_ARRAY_BUFFER));
+            accessors.push(format!(
+                r#"{{"bufferView":{},"componentType":{GL_FLOAT},"count":{count},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+                views.len() - 1, min.x, min.y, min.z, max.x, max.y, max.z,
+            ));
+
+            views.push(push_view(&mut bin, mesh.normals().iter().flatten().flat_map(|v| v.to_le_bytes()), GL_ARRAY_BUFFER));
+            accessors.push(format!(r#"{{"bufferView":{},"componentType":{GL_FLOAT},"count":{count},"type":"VEC3"}}"#, views.len() - 1));
+
+            views.push(push_view(&mut bin, mesh.colours().iter().flatten().copied(), GL_ARRAY_BUFFER));
+            accessors.push(format!(r#"{{"bufferView":{},"componentType":{GL_UNSIGNED_BYTE},"normalized":true,"count":{count},"type":"VEC4"}}"#, views.len() - 1));
+
+            views.push(push_view(&mut bin, mesh.indices().iter().flat_map(|v| v.to_le_bytes()), GL_ELEMENT_ARRAY_BUFFER));
+            accessors.push(format!(r#"{{"bufferView":{},"componentType":{GL_UNSIGNED_INT},"count":{},"type":"SCALAR"}}"#, views.len() - 1, mesh.indices().len()));
+
+            meshes.push(format!(
+                r#"{{"primitives":[{{"attributes":{{"POSITION":{},"NORMAL":{},"COLOR_0":{}}},"indices":{},"mode":4}}]}}"#,
+                first, first + 1, first + 2, first + 3,
+            ));
+            let _ = write!(json_node, r#","mesh":{}"#, meshes.len() - 1);
+        }
+
+        json_node.push('}');
+        json_nodes.push(json_node);
+    }
+
+    let mut json = String::from(r#"{"asset":{"version":"2.0","generator":"nvm_v3d"},"scene":0,"scenes":[{"#);
+    if !nodes.is_empty() {
+        let _ = write!(json, r#""nodes":[{}]"#, (0..nodes.len()).map(|i| i.to_string()).collect::<Vec<_>>().join(","));
+    }
+    json.push_str("}]");
+    for (name, entries) in [("nodes", &json_nodes), ("meshes", &meshes), ("accessors", &accessors), ("bufferViews", &views)] {
+        if !entries.is_empty() {
+            let _ = write!(json, r#","{name}":[{}]"#, entries.join(","));
+        }
+    }
+    if !bin.is_empty() {
+        let _ = write!(json, r#","buffers":[{{"byteLength":{}}}]"#, bin.len());
+    }
+    json.push('}');
+
+    // Chunks are padded to 4 bytes, JSON with spaces
+    let mut json = json.into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let bin_size = if bin.is_empty() { 0 } else { 8 + bin.len() };
+    let total    = 12 + 8 + json.len() + bin_size;
+    for v in [GLB_MAGIC, GLB_VERSION, total as u32, json.len() as u32, GLB_CHUNK_JSON] {
+        dest.write_all(&v.to_le_bytes())?;
+    }
+    dest.write_all(&json)?;
+    if !bin.is_empty() {
+        dest.write_all(&(bin.len() as u32).to_le_bytes())?;
+        dest.write_all(&GLB_CHUNK_BIN.to_le_bytes())?;
+        dest.write_all(&bin)?;
+    }
+    Ok(())
+}
+
+/// Appends the bytes to the buffer and returns the buffer view over them.
+/// Every attribute is a multiple of 4 bytes, so views stay aligned.
+fn push_view(bin: &mut Vec<u8>, bytes: impl Iterator<Item = u8>, target: u32) -> String {
+    let offset = bin.len();
+    bin.extend(bytes);
+    format!(r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{},"target":{target}}}"#, bin.len() - offset)
+}
+
+fn escape_json(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"'  => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if c.is_control() => { let _ = write!(result, "\\u{:04x}", c as u32); },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::meshing::{VertexLayout, VisFace};
+
+    use super::*;
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn chunks_are_consistent() {
+        let layout = VertexLayout::DEFAULT;
+        let mut mesh = ExportMesh::default();
+        mesh.add_faces(layout, &VisFace::ALL.map(|face| layout.encode(1, 2, 3, face)), |_| ExportMesh::WHITE).unwrap();
+        let empty = ExportMesh::default();
+
+        let nodes = [ExportNode::for_chunk(PosChunk::new(1, -2, 3), &mesh), ExportNode::new("empty \"node\"", Vec3::ZERO, &empty)];
+        let mut dest = Vec::new();
+        write_glb(&mut dest, &nodes).unwrap();
+
+        assert_eq!(read_u32(&dest, 0), GLB_MAGIC);
+        assert_eq!(read_u32(&dest, 4), GLB_VERSION);
+        assert_eq!(read_u32(&dest, 8) as usize, dest.len());
+        assert_eq!(dest.len() % 4, 0);
+
+        let json_len = read_u32(&dest, 12) as usize;
+        assert_eq!(read_u32(&dest, 16), GLB_CHUNK_JSON);
+        assert_eq!(json_len % 4, 0);
+        let json = core::str::from_utf8(&dest[20..20 + json_len]).unwrap();
+        assert!(json.contains(r#""translation":[32,-64,96]"#));
+        assert!(json.contains(r#""name":"empty \"node\"""#));
+
+        // A 12 byte position and normal and a 4 byte colour per vertex, then the indices
+        let bin_start = 20 + json_len;
+        let bin_len   = read_u32(&dest, bin_start) as usize;
+        let expected  = 24*(12 + 12 + 4) + 36*4;
+        assert_eq!(read_u32(&dest, bin_start + 4), GLB_CHUNK_BIN);
+        assert_eq!(bin_len, expected);
+        assert_eq!(bin_start + 8 + bin_len, dest.len());
+        assert!(json.contains(&format!(r#""buffers":[{{"byteLength":{expected}}}]"#)));
+    }
+
+    #[test]
+    fn empty_scene_has_no_bin_chunk() {
+        let mut dest = Vec::new();
+        write_glb(&mut dest, &[]).unwrap();
+        assert_eq!(read_u32(&dest, 8) as usize, dest.len());
+        assert_eq!(20 + read_u32(&dest, 12) as usize, dest.len());
+    }
+
+}
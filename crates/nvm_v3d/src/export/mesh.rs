@@ -0,0 +1,237 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use std::collections::HashMap;
+
+use glam::{Affine3A, Vec3};
+
+use crate::meshing::{ChunkMesh, SmoothMesh, VertexError, VertexLayout, create_quad_for_vertex_sized};
+
+/// Bits of a vertex's position and normal, with its colour.
+type VertexKey = ([u32; 3], [u32; 3], [u8; 4]);
+
+/// Indexed triangle mesh ready to be written by the exporters, with one
+/// normal and colour per vertex.
+///
+/// Faces are added in their encoded form and split into triangles, merging
+/// corners that share a position, normal and colour.
+#[derive(Debug, Default, Clone)]
+pub struct ExportMesh {
+    positions: Vec<[f32; 3]>,
+    normals:   Vec<[f32; 3]>,
+    colours:   Vec<[u8; 4]>,
+    indices:   Vec<u32>,
+}
+
+impl ExportMesh {
+
+    pub const WHITE: [u8; 4] = [u8::MAX; 4];
+
+    /// Adds the faces of a chunk mesh, coloured per face, see
+    /// [`Self::add_faces`].
+    pub fn add_chunk_mesh(&mut self, mesh: &ChunkMesh, colour: impl FnMut(u32) -> [u8; 4]) -> Result<(), VertexError> {
+        self.add_faces(mesh.layout(), mesh.faces(), colour)
+    }
+
+    /// Adds faces encoded with [`VertexLayout::encode_sized`], coloured per
+    /// face. Nothing is added if any face can't be decoded.
+    pub fn add_faces(&mut self, layout: VertexLayout, faces: &[u32], mut colour: impl FnMut(u32) -> [u8; 4]) -> Result<(), VertexError> {
+        let (vertex_count, index_count) = (self.positions.len(), self.indices.len());
+        let mut lookup = HashMap::new();
+        for &face in faces {
+            let (x, y, layer, vis_face) = match layout.try_decode(face) {
+                Ok(result) => result,
+                Err(error) => {
+                    self.truncate(vertex_count, index_count);
+                    return Err(error);
+                },
+            };
+            let (width, height) = layout.decode_size(face);
+            let quad = create_quad_for_vertex_sized(x, y, layer, vis_face, width, height).map(|v| v.as_vec3());
+            self.add_quad(&mut lookup, quad, colour(face));
+        }
+        Ok(())
+    }
+
+    /// Adds faces encoded with [`VertexLayout::encode_shape`], coloured per
+    /// face.
+    pub fn add_shape_faces(&mut self, layout: VertexLayout, faces: &[u32], mut colour: impl FnMut(u32) -> [u8; 4]) {
+        let mut lookup = HashMap::new();
+        for &face in faces {
+            self.add_quad(&mut lookup, layout.create_quad_for_shape(face), colour(face));
+        }
+    }
+
+    /// Adds an already indexed smooth mesh in a single colour.
+    pub fn add_smooth_mesh<const SIZE: usize>(&mut self, mesh: &SmoothMesh<SIZE>, colour: [u8; 4]) {
+        let base = self.positions.len() as u32;
+        self.positions.extend_from_slice(mesh.positions());
+        self.normals.extend_from_slice(mesh.normals());
+        self.colours.resize(self.positions.len(), colour);
+        self.indices.extend(mesh.indices().iter().map(|i| base + i));
+    }
+
+    /// Adds another mesh, moving its positions by `offset`.
+    pub fn append(&mut self, other: &Self, offset: Vec3) {
+        let base = self.positions.len() as u32;
+        self.positions.extend(other.positions.iter().map(|&p| (Vec3::from(p) + offset).to_array()));
+        self.normals.extend_from_slice(&other.normals);
+        self.colours.extend_from_slice(&other.colours);
+        self.indices.extend(other.indices.iter().map(|i| base + i));
+    }
+
+    /// Transforms every position and normal, eg. to convert the world's Z-up
+    /// coordinates for a Y-up tool.
+    pub fn transform(&mut self, transform: Affine3A) {
+        for position in &mut self.positions {
+            *position = transform.transform_point3(Vec3::from(*position)).to_array();
+        }
+        for normal in &mut self.normals {
+            *normal = transform.transform_vector3(Vec3::from(*normal)).normalize_or_zero().to_array();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.normals.clear();
+        self.colours.clear();
+        self.indices.clear();
+    }
+
+    #[must_use]
+    pub fn positions(&self) -> &[[f32; 3]] {
+        &self.positions
+    }
+
+    #[must_use]
+    pub fn normals(&self) -> &[[f32; 3]] {
+        &self.normals
+    }
+
+    #[must_use]
+    pub fn colours(&self) -> &[[u8; 4]] {
+        &self.colours
+    }
+
+    /// Counter-clockwise triangles, seen from the front.
+    #[must_use]
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Number of triangles.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Returns the smallest and largest position, or `None` when empty.
+    #[must_use]
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let first = Vec3::from(*self.positions.first()?);
+        Some(self.positions.iter().fold((first, first), |(min, max), &p| (min.min(p.into()), max.max(p.into()))))
+    }
+
+    fn truncate(&mut self, vertex_count: usize, index_count: usize) {
+        self.positions.truncate(vertex_count);
+        self.normals.truncate(vertex_count);
+        self.colours.truncate(vertex_count);
+        self.indices.truncate(index_count);
+    }
+
+    fn add_quad(&mut self, lookup: &mut HashMap<VertexKey, u32>, quad: [Vec3; 6], colour: [u8; 4]) {
+        // Adding zero folds negative zeros, so equal normals share their bits
+        let normal = (quad[1] - quad[0]).cross(quad[2] - quad[0]).normalize_or_zero() + Vec3::ZERO;
+        for position in quad {
+            let key = (position.to_array().map(f32::to_bits), normal.to_array().map(f32::to_bits), colour);
+            let index = *lookup.entry(key).or_insert_with(|| {
+                self.positions.push(position.to_array());
+                self.normals.push(normal.to_array());
+                self.colours.push(colour);
+                (self.positions.len() - 1) as u32
+            });
+            self.indices.push(index);
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{meshing::{ChunkMeshMode, ShapeFace, VisFace}, tiles::{ShapeBox, TileDefinition, TileRegistry}, world::{ChunkStorage, PosBlock}};
+
+    use super::*;
+
+    fn export_voxel<const SIZE: usize>(pos: PosBlock<SIZE>) -> ExportMesh {
+        let mut registry = TileRegistry::new();
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+        let mut storage = ChunkStorage::<SIZE>::new_empty();
+        storage.update(pos, stone, &registry);
+
+        let mut chunk_mesh = ChunkMesh::default();
+        chunk_mesh.build(&storage);
+        let mut mesh = ExportMesh::default();
+        mesh.add_chunk_mesh(&chunk_mesh, |_| ExportMesh::WHITE).unwrap();
+        mesh
+    }
+
+    #[test]
+    fn exports_each_chunk_size() {
+        for (mesh, pos) in [
+            (export_voxel(<PosBlock<16>>::new(12, 13, 14)), Vec3::new(12.0, 13.0, 14.0)),
+            (export_voxel(<PosBlock<64>>::new(40, 50, 60)), Vec3::new(40.0, 50.0, 60.0)),
+        ] {
+            assert_eq!(mesh.len(), 12);
+            assert_eq!(mesh.positions().len(), 24);
+            assert_eq!(mesh.bounds(), Some((pos, pos + Vec3::ONE)));
+        }
+    }
+
+    #[test]
+    fn merges_shared_corners() {
+        let mut registry = TileRegistry::new();
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+        let mut storage = <ChunkStorage<16>>::new_empty();
+        for idx in 0..8 {
+            storage.update(PosBlock::new(idx & 1, (idx >> 1) & 1, idx >> 2), stone, &registry);
+        }
+
+        // Each 2x2 side has 9 corners when culled, 4 when merged, none shared across sides
+        for (mode, triangles, vertices) in [(ChunkMeshMode::Culled, 48, 54), (ChunkMeshMode::Greedy, 12, 24)] {
+            let mut chunk_mesh = ChunkMesh::new(mode);
+            chunk_mesh.build(&storage);
+            let mut mesh = ExportMesh::default();
+            mesh.add_chunk_mesh(&chunk_mesh, |_| ExportMesh::WHITE).unwrap();
+            assert_eq!((mesh.len(), mesh.positions().len()), (triangles, vertices), "{mode:?}");
+            assert_eq!(mesh.bounds(), Some((Vec3::ZERO, Vec3::splat(2.0))));
+
+            // Colours split corners too
+            let mut coloured = ExportMesh::default();
+            coloured.add_chunk_mesh(&chunk_mesh, |face| [face as u8, 0, 0, 255]).unwrap();
+            assert_eq!(coloured.positions().len(), chunk_mesh.len()*4, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn shape_faces_use_layout() {
+        let layout = VertexLayout::of::<64>();
+        let mut mesh = ExportMesh::default();
+        mesh.add_shape_faces(layout, &[layout.encode_shape(40, 50, 60, ShapeFace::PosY, ShapeBox::new([0, 0, 0], [2, 1, 2]), false)], |_| ExportMesh::WHITE);
+        assert_eq!(mesh.bounds(), Some((Vec3::new(40.0, 50.0, 60.0), Vec3::new(41.0, 50.0, 61.0))));
+    }
+
+    #[test]
+    fn rejects_corrupt_faces() {
+        let layout = VertexLayout::of::<16>();
+        let faces  = [layout.encode(1, 2, 3, VisFace::PosZ), 0x07 << (3*layout.coord_bits())];
+        let mut mesh = ExportMesh::default();
+        assert_eq!(mesh.add_faces(layout, &faces, |_| ExportMesh::WHITE), Err(VertexError::InvalidFace(7)));
+        assert!(mesh.is_empty());
+        assert!(mesh.positions().is_empty());
+    }
+
+}
@@ -0,0 +1,13 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+mod mesh;
+pub use mesh::*;
+
+mod obj;
+pub use obj::*;
+
+mod ply;
+pub use ply::*;
+
+mod gltf;
+pub use gltf::*;
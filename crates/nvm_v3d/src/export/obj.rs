@@ -0,0 +1,48 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use std::io::{self, Write};
+
+use super::ExportMesh;
+
+/// Writes the mesh as Wavefront OBJ, with vertex colours appended to each
+/// position as most tools expect.
+pub fn write_obj(dest: &mut impl Write, mesh: &ExportMesh) -> io::Result<()> {
+    for (position, colour) in mesh.positions().iter().zip(mesh.colours()) {
+        let [r, g, b, _] = colour.map(|v| f32::from(v) / 255.0);
+        writeln!(dest, "v {} {} {} {r} {g} {b}", position[0], position[1], position[2])?;
+    }
+    for normal in mesh.normals() {
+        writeln!(dest, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+    }
+    // Every position has its own normal, so both share an index
+    for triangle in mesh.indices().chunks_exact(3) {
+        let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+        writeln!(dest, "f {a}//{a} {b}//{b} {c}//{c}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::meshing::{VertexLayout, VisFace};
+
+    use super::*;
+
+    #[test]
+    fn writes_every_element() {
+        let layout = VertexLayout::DEFAULT;
+        let mut mesh = ExportMesh::default();
+        mesh.add_faces(layout, &VisFace::ALL.map(|face| layout.encode(1, 2, 3, face)), |_| ExportMesh::WHITE).unwrap();
+
+        let mut dest = Vec::new();
+        write_obj(&mut dest, &mesh).unwrap();
+        let obj = String::from_utf8(dest).unwrap();
+        let count = |prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+        assert_eq!((count("v "), count("vn "), count("f ")), (24, 24, 12));
+        assert!(obj.lines().filter(|line| line.starts_with("f ")).flat_map(|line| line.split(' ').skip(1)).all(|v| {
+            let (a, b) = v.split_once("//").unwrap();
+            a == b && (1..=24).contains(&a.parse::<u32>().unwrap())
+        }));
+    }
+
+}
@@ -0,0 +1,74 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use std::io::{self, Write};
+
+use super::ExportMesh;
+
+/// Writes the mesh as little-endian binary PLY, with a position, normal and
+/// RGBA colour per vertex.
+pub fn write_ply(dest: &mut impl Write, mesh: &ExportMesh) -> io::Result<()> {
+    write!(dest, concat!(
+            "ply\n",
+            "format binary_little_endian 1.0\n",
+            "element vertex {}\n",
+            "property float x\n",
+            "property float y\n",
+            "property float z\n",
+            "property float nx\n",
+            "property float ny\n",
+            "property float nz\n",
+            "property uchar red\n",
+            "property uchar green\n",
+            "property uchar blue\n",
+            "property uchar alpha\n",
+            "element face {}\n",
+            "property list uchar uint vertex_indices\n",
+            "end_header\n",
+        ),
+        mesh.positions().len(),
+        mesh.len(),
+    )?;
+
+    for ((position, normal), colour) in mesh.positions().iter().zip(mesh.normals()).zip(mesh.colours()) {
+        for v in position.iter().chain(normal) {
+            dest.write_all(&v.to_le_bytes())?;
+        }
+        dest.write_all(colour)?;
+    }
+
+    for triangle in mesh.indices().chunks_exact(3) {
+        dest.write_all(&[3])?;
+        for i in triangle {
+            dest.write_all(&i.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::meshing::{VertexLayout, VisFace};
+
+    use super::*;
+
+    #[test]
+    fn header_matches_body() {
+        let layout = VertexLayout::DEFAULT;
+        let mut mesh = ExportMesh::default();
+        mesh.add_faces(layout, &VisFace::ALL.map(|face| layout.encode(1, 2, 3, face)), |_| ExportMesh::WHITE).unwrap();
+
+        let mut dest = Vec::new();
+        write_ply(&mut dest, &mesh).unwrap();
+
+        let end    = b"end_header\n";
+        let split  = dest.windows(end.len()).position(|v| v == end).unwrap() + end.len();
+        let header = core::str::from_utf8(&dest[..split]).unwrap();
+        assert!(header.starts_with("ply\nformat binary_little_endian 1.0\n"));
+        assert!(header.contains("\nelement vertex 24\n"));
+        assert!(header.contains("\nelement face 12\n"));
+
+        // Six floats and four colour bytes per vertex, a count and three indices per face
+        assert_eq!(dest.len() - split, 24*(6*4 + 4) + 12*(1 + 3*4));
+    }
+
+}
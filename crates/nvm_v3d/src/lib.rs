@@ -1,9 +1,10 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
-#![feature(const_option)]
 #![feature(const_trait_impl)]
 
 pub mod world;
 pub mod meshing;
 pub mod tiles;
 pub mod lighting;
+pub mod persist;
+pub mod export;
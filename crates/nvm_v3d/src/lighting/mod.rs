@@ -1,6 +1,6 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
-use crate::world::PosWorld;
+use crate::{tiles::TileRegistry, world::{PosBlock, PosChunk, World, WorldPosition, CHUNK_LENGTH}};
 
 mod storage_chunk;
 pub use storage_chunk::*;
@@ -10,26 +10,52 @@ pub use storage_world::*;
 
 pub mod update;
 
-pub fn light_sunlight_raise_batched(
-    get_transmission:  &mut impl FnMut(PosWorld, usize) -> u8,
-    storage: &mut LightStorageWorld,
-    updates: &[(PosWorld, u8)]
+pub fn light_sunlight_raise_batched<P: WorldPosition>(
+    world:    &World,
+    registry: &TileRegistry,
+    storage:  &mut LightStorageWorld,
+    updates:  &[(P, u8)]
 ) {
-    let mut queue = Vec::<PosWorld>::with_capacity(updates.len()); // TODO OPT probably larger
+    let mut queue = Vec::<P>::with_capacity(updates.len()); // TODO OPT probably larger
     update::light_channel_raise_batched(3, storage, updates.iter().copied(), &mut queue);
-    update::light_channel_raise_propogate(get_transmission, 3, storage, &mut queue);
+    update::light_channel_raise_propogate(world, registry, 3, storage, &mut queue);
 }
 
-pub fn light_blocklight_raise_batched(
-    get_transmission:  &mut impl FnMut(PosWorld, usize) -> u8,
-    storage: &mut LightStorageWorld,
-    updates: &[(PosWorld, [u8; 3])]
+pub fn light_blocklight_raise_batched<P: WorldPosition>(
+    world:    &World,
+    registry: &TileRegistry,
+    storage:  &mut LightStorageWorld,
+    updates:  &[(P, [u8; 3])]
 ) -> usize {
     let mut update_count = 0;
-    let mut queue = Vec::<PosWorld>::with_capacity(updates.len()); // TODO OPT probably larger
+    let mut queue = Vec::<P>::with_capacity(updates.len()); // TODO OPT probably larger
     for i in 0..3 {
         update_count += update::light_channel_raise_batched(i, storage, updates.iter().map(|&(pos, value)| (pos, value[i])), &mut queue);
-        update_count += update::light_channel_raise_propogate(get_transmission, i, storage, &mut queue);
+        update_count += update::light_channel_raise_propogate(world, registry, i, storage, &mut queue);
     }
     update_count
 }
+
+/// Collects every light emitting tile in the chunk, as updates suitable for
+/// [`light_blocklight_raise_batched`].
+pub fn light_collect_emitters<P: WorldPosition>(
+    world:     &World,
+    registry:  &TileRegistry,
+    pos_chunk: PosChunk,
+    dest:      &mut Vec<(P, [u8; 3])>,
+) {
+    let Some(chunk) = world.get_chunk(pos_chunk) else { return; };
+    if let Some(id) = chunk.get_uniform() {
+        if registry.get_emission(id) == [0; 3] {
+            return;
+        }
+    }
+
+    for idx in 0..CHUNK_LENGTH {
+        let pos_block = PosBlock::from_idx(idx);
+        let emission  = registry.get_emission(chunk.get(pos_block));
+        if emission != [0; 3] {
+            dest.push((P::from_chunk_and_block(pos_chunk, pos_block), emission));
+        }
+    }
+}
@@ -1,11 +1,22 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
-use crate::world::CHUNK_LENGTH;
+use crate::world::{ChunkDimensions, CHUNK_SIZE};
 
+/// Light channels of every voxel of a chunk `SIZE` voxels across, indexed
+/// like [`crate::world::PosBlock::to_idx`].
 #[derive(Debug)]
-pub struct LightStorageChunk(Box<[[u8; 4]; CHUNK_LENGTH]>);
+pub struct LightStorageChunk<const SIZE: usize = CHUNK_SIZE>(Box<[[u8; 4]]>);
+
+impl<const SIZE: usize> LightStorageChunk<SIZE> {
+
+    const LENGTH: usize = ChunkDimensions::<SIZE>::LENGTH;
+
+    #[must_use]
+    pub fn from_data(data: Box<[[u8; 4]]>) -> Self {
+        assert_eq!(data.len(), Self::LENGTH, "Expected light data for every voxel");
+        Self(data)
+    }
 
-impl LightStorageChunk {
     pub fn raise_channel(&mut self, idx: usize, channel: usize, value: u8) -> bool {
         let current = self.0[idx][channel];
         if current < value {
@@ -22,15 +33,14 @@ impl LightStorageChunk {
     }
 
     #[must_use] 
-    pub const fn get_data(&self) -> &[[u8; 4]; CHUNK_LENGTH] {
+    pub const fn get_data(&self) -> &[[u8; 4]] {
         &self.0
     }
 }
 
 
-impl Default for LightStorageChunk {
+impl<const SIZE: usize> Default for LightStorageChunk<SIZE> {
     fn default() -> Self {
-        Self(vec![[0; 4]; CHUNK_LENGTH].into_boxed_slice().try_into().unwrap())
+        Self(vec![[0; 4]; Self::LENGTH].into_boxed_slice())
     }
 }
-
@@ -2,7 +2,7 @@
 
 use std::collections::{hash_map::Entry, HashMap};
 
-use crate::world::{PosChunk, PosWorld};
+use crate::world::{PosChunk, WorldPosition};
 
 use super::LightStorageChunk;
 
@@ -12,7 +12,7 @@ pub struct LightStorageWorld(HashMap<PosChunk, LightStorageChunk>);
 impl LightStorageWorld {
 
     #[must_use] 
-    pub fn get_channel(&self, pos: PosWorld, channel: usize) -> u8 {
+    pub fn get_channel(&self, pos: impl WorldPosition, channel: usize) -> u8 {
         let (pos_chunk, pos_block) = pos.to_chunk_and_block();
         let idx = pos_block.to_idx();
         self.0.get(&pos_chunk).map_or(0, |chunk| chunk.get_channel(idx, channel))
@@ -30,4 +30,12 @@ impl LightStorageWorld {
         self.0.get(&pos_chunk)
     }
 
+    pub fn insert_chunk(&mut self, pos_chunk: PosChunk, chunk: LightStorageChunk) -> Option<LightStorageChunk> {
+        self.0.insert(pos_chunk, chunk)
+    }
+
+    pub fn remove_chunk(&mut self, pos_chunk: PosChunk) -> Option<LightStorageChunk> {
+        self.0.remove(&pos_chunk)
+    }
+
 }
\ No newline at end of file
@@ -1,14 +1,14 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
-use crate::world::PosWorld;
+use crate::{meshing::VisFace, tiles::TileRegistry, world::{World, WorldPosition}};
 
 use super::LightStorageWorld;
 
-pub fn light_channel_raise_batched(
+pub fn light_channel_raise_batched<P: WorldPosition>(
     channel: usize,
     storage: &mut LightStorageWorld,
-    updates: impl IntoIterator<Item = (PosWorld, u8)>,
-    queue:   &mut Vec<PosWorld>,
+    updates: impl IntoIterator<Item = (P, u8)>,
+    queue:   &mut Vec<P>,
 ) -> usize {
     let mut update_count = 0;
     for (pos, target) in updates {
@@ -26,11 +26,12 @@ pub fn light_channel_raise_batched(
     update_count
 }
 
-pub fn light_channel_raise_propogate(
-    get_transmission:  &mut impl FnMut(PosWorld, usize) -> u8,
-    channel: usize,
-    storage: &mut LightStorageWorld,
-    queue:   &mut Vec<PosWorld>,
+pub fn light_channel_raise_propogate<P: WorldPosition>(
+    world:    &World,
+    registry: &TileRegistry,
+    channel:  usize,
+    storage:  &mut LightStorageWorld,
+    queue:    &mut Vec<P>,
 ) -> usize {
     let mut update_count = 0;
     while let Some(pos) = queue.pop() {
@@ -41,7 +42,7 @@ pub fn light_channel_raise_propogate(
         let values = neighbourhood.map(|neighbour| storage.get_channel(neighbour, channel));
 
         // Recalculate light value from neighbours
-        let transmit_cost = (get_transmission)(pos, channel).saturating_add(1); // TODO Get from block transmittance
+        let transmit_cost = registry.get_transmission(world.get(pos), channel).saturating_add(1);
         let target = values.iter().max().unwrap().saturating_sub(transmit_cost);
 
         // Update light value
@@ -63,15 +64,8 @@ pub fn light_channel_raise_propogate(
     update_count
 }
 
-const fn get_light_neighbourhood_of(pos: PosWorld) -> [PosWorld; 6] {
-    [
-        pos.with_offset( 1,  0,  0),
-        pos.with_offset(-1,  0,  0),
-        pos.with_offset( 0,  1,  0),
-        pos.with_offset( 0, -1,  0),
-        pos.with_offset( 0,  0,  1),
-        pos.with_offset( 0,  0, -1),
-    ]
+fn get_light_neighbourhood_of<P: WorldPosition>(pos: P) -> [P; 6] {
+    VisFace::ALL.map(|face| pos + face.offset())
 }
 
 const fn could_transmit_to(from: u8, to: u8) -> bool {
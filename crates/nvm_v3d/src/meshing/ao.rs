@@ -0,0 +1,153 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use glam::IVec3;
+
+use crate::tiles::TileIdentifier;
+
+use super::{FaceTileProvider, FaceVisibilityProvider, VisFace, get_face_basis, get_face_tile};
+
+/// Provides the voxels that darken neighbouring faces for ambient occlusion.
+pub trait FaceOcclusionProvider {
+    /// Returns if the voxel at the given chunk-local position occludes. The
+    /// position may lie up to one voxel outside of the chunk.
+    fn is_face_occluder(&self, pos: [isize; 3]) -> bool;
+}
+
+/// Meshes a plane like [`super::mesh_chunk_plane_tiled`], also packing the
+/// ambient occlusion of each face corner with [`super::VertexLayout::with_ao`].
+pub fn mesh_chunk_plane_ao<T: FaceVisibilityProvider + FaceTileProvider + FaceOcclusionProvider>(
+    vis:        &T,
+    chunk:      [usize; 3],
+    face:       VisFace,
+    layer:      u32,
+    dest:       &mut [u32],
+    dest_tiles: &mut [TileIdentifier],
+) -> usize {
+    const { assert!(T::VERTEX_LAYOUT.has_ao(), "Ambient occlusion doesn't fit the vertex layout of the chunk size") };
+    assert!(dest.len() >= 8*8);
+    assert!(dest_tiles.len() >= 8*8);
+
+    let mut visibility = vis.get_face_visibility_plane(chunk, face, layer).to_raw();
+    let offset = face.axis().to_local_usize(chunk).map(|v| (v*8) as u8);
+
+    let mut size = 0;
+    while visibility != 0 {
+        let i = visibility.trailing_zeros();
+        visibility &= visibility - 1;
+        let vert = T::VERTEX_LAYOUT.encode(
+            offset[0] + (i & 0x07) as u8,
+            offset[1] + (i >>   3) as u8,
+            offset[2] +      layer as u8,
+            face
+        );
+        dest[size] = T::VERTEX_LAYOUT.with_ao(vert, get_face_ao(vis, chunk, face, layer, i));
+        dest_tiles[size] = get_face_tile(vis, chunk, face, layer, i);
+        size += 1;
+    }
+
+    size
+}
+
+/// Computes the packed ambient occlusion of bit `i` of a face visibility
+/// plane from the side and corner voxels in front of each face corner.
+pub(crate) fn get_face_ao(occlusion: &impl FaceOcclusionProvider, chunk: [usize; 3], face: VisFace, layer: u32, i: u32) -> u8 {
+    let local = [(i & 0x07) as usize, (i >> 3) as usize, layer as usize];
+    let world = face.axis().to_world_usize(local);
+    let basis = get_face_basis(face);
+
+    let pos = IVec3::new(
+        (chunk[0]*8 + world[0]) as i32,
+        (chunk[1]*8 + world[1]) as i32,
+        (chunk[2]*8 + world[2]) as i32,
+    );
+    // The voxel that would cull this face
+    let front = pos - basis[2];
+    let is_occluder = |pos: IVec3| occlusion.is_face_occluder([pos.x as isize, pos.y as isize, pos.z as isize]);
+
+    let mut result = 0;
+    for (corner, (u, v)) in [(-1, -1), (1, -1), (-1, 1), (1, 1)].into_iter().enumerate() {
+        let side_u = is_occluder(front + basis[0]*u);
+        let side_v = is_occluder(front + basis[1]*v);
+        let ao = if side_u && side_v {
+            0
+        } else {
+            3 - u8::from(side_u) - u8::from(side_v) - u8::from(is_occluder(front + basis[0]*u + basis[1]*v))
+        };
+        result |= ao << (2*corner);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{meshing::{ChunkMesh, ChunkMeshMode}, tiles::{TileDefinition, TileRegistry}, world::{ChunkStorage, PosBlock}};
+
+    use super::*;
+
+    struct Occluders(Vec<IVec3>);
+
+    impl FaceOcclusionProvider for Occluders {
+        fn is_face_occluder(&self, pos: [isize; 3]) -> bool {
+            self.0.contains(&IVec3::new(pos[0] as i32, pos[1] as i32, pos[2] as i32))
+        }
+    }
+
+    /// Corners of the `PosZ` face of the voxel at (1, 1, 1), which lies in
+    /// front of the voxels at z = 0.
+    fn corners(occluders: &[[i32; 3]]) -> [u8; 4] {
+        let ao = get_face_ao(&Occluders(occluders.iter().copied().map(IVec3::from_array).collect()), [0, 0, 0], VisFace::PosZ, 1, 9);
+        [0, 1, 2, 3].map(|corner| (ao >> (2*corner)) & 0x03)
+    }
+
+    #[test]
+    fn face_corner_values() {
+        assert_eq!(corners(&[]), [3, 3, 3, 3]);
+        assert_eq!(corners(&[[1, 1, 1]]), [3, 3, 3, 3]);
+        assert_eq!(corners(&[[0, 0, 0]]), [2, 3, 3, 3]);
+        assert_eq!(corners(&[[0, 1, 0]]), [2, 3, 2, 3]);
+        assert_eq!(corners(&[[0, 1, 0], [2, 2, 0]]), [2, 3, 2, 2]);
+        assert_eq!(corners(&[[0, 1, 0], [0, 0, 0], [0, 2, 0]]), [1, 3, 1, 3]);
+
+        // Two sides meeting at a corner fully occlude it, with or without the corner voxel
+        assert_eq!(corners(&[[0, 1, 0], [1, 0, 0]]), [0, 2, 2, 3]);
+        assert_eq!(corners(&[[0, 1, 0], [1, 0, 0], [0, 0, 0]]), [0, 2, 2, 3]);
+    }
+
+    #[test]
+    fn greedy_only_merges_equal_ao() {
+        let mut registry = TileRegistry::new();
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+        let mut storage = <ChunkStorage<16>>::new_empty();
+        for idx in 0..16*16*16 {
+            let pos = <PosBlock<16>>::from_idx(idx);
+            if pos.y < 4 + pos.x/3 || (pos.y < 10 && (pos.x + pos.z*2) % 9 == 0) {
+                storage.update(pos, stone, &registry);
+            }
+        }
+
+        let mut culled = ChunkMesh::new(ChunkMeshMode::Culled);
+        let mut greedy = ChunkMesh::new(ChunkMeshMode::Greedy);
+        culled.build_ao(&storage);
+        greedy.build_ao(&storage);
+        let layout = culled.layout();
+
+        let unit_ao: HashMap<_, _> = culled.faces().iter().map(|&vert| (layout.decode(vert), layout.decode_ao(vert))).collect();
+        assert!(unit_ao.values().any(|&ao| ao != [3; 4]));
+
+        let mut merged = 0;
+        for &vert in greedy.faces() {
+            let (x, y, layer, face) = layout.decode(vert);
+            let (width, height) = layout.decode_size(vert);
+            merged += usize::from(width*height > 1);
+            for u in x..x + width {
+                for v in y..y + height {
+                    assert_eq!(unit_ao[&(u, v, layer, face)], layout.decode_ao(vert), "{face:?} {u} {v} {layer}");
+                }
+            }
+        }
+        assert_ne!(merged, 0);
+    }
+
+}
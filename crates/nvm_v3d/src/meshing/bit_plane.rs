@@ -34,6 +34,11 @@ impl Not for BitPlane {
 }
 
 impl BitPlane {
+    #[must_use]
+    pub const fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
     #[must_use]
     pub const fn to_raw(self) -> u64 {
         self.0
@@ -41,11 +46,11 @@ impl BitPlane {
 }
 
 impl BitPlane {
-    pub fn push(&mut self) {
+    pub const fn push(&mut self) {
         self.0 = (self.0 << 1) | 1;
     }
 
-    pub fn skip(&mut self) {
+    pub const fn skip(&mut self) {
         self.0 <<= 1;
     }
 }
@@ -53,6 +58,7 @@ impl BitPlane {
 impl BitPlane {
 
     pub const DEFAULT: Self = Self(0);
+    pub const FULL:    Self = Self(u64::MAX);
 
     pub fn set(&mut self, idx: u32, state: bool) {
         debug_assert!(Self::is_idx_valid(idx));
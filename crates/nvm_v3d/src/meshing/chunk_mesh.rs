@@ -0,0 +1,374 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use core::ops::Range;
+
+use crate::tiles::TileIdentifier;
+
+use super::{FaceOcclusionProvider, FaceTileProvider, FaceVisibilityProvider, VertexLayout, VisFace, mesh_chunk_plane, mesh_chunk_plane_ao, mesh_chunk_plane_greedy, mesh_chunk_plane_greedy_ao, mesh_chunk_plane_greedy_tiled, mesh_chunk_plane_tiled};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChunkMeshMode {
+    /// One face per visible voxel face, see [`mesh_chunk_plane`].
+    #[default]
+    Culled,
+    /// Adjacent faces merged into rectangles, see [`mesh_chunk_plane_greedy`].
+    Greedy,
+}
+
+/// Faces of a whole chunk, grouped into one contiguous range per [`VisFace`]
+/// so renderers can skip directions facing away from the camera.
+///
+/// Rebuilding a mesh reuses its buffers, so keeping one `ChunkMesh` per
+/// worker avoids reallocating for every chunk. Faces are encoded with the
+/// [`VertexLayout`] of the last chunk built.
+#[derive(Debug, Default, Clone)]
+pub struct ChunkMesh {
+    mode:   ChunkMeshMode,
+    layout: VertexLayout,
+    faces:  Vec<u32>,
+    tiles:  Vec<TileIdentifier>,
+    ranges: [Range<usize>; 6],
+}
+
+impl ChunkMesh {
+
+    #[must_use]
+    pub fn new(mode: ChunkMeshMode) -> Self {
+        Self{
+            mode,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub const fn mode(&self) -> ChunkMeshMode {
+        self.mode
+    }
+
+    pub const fn set_mode(&mut self, mode: ChunkMeshMode) {
+        self.mode = mode;
+    }
+
+    /// Meshes every visible face of the chunk, replacing the current faces.
+    pub fn build<T: FaceVisibilityProvider>(&mut self, vis: &T) {
+        let mode = self.mode;
+        self.build_with(T::VERTEX_LAYOUT, false, |chunk, face, layer, dest, _| match mode {
+            ChunkMeshMode::Culled => mesh_chunk_plane(vis, chunk, face, layer, dest),
+            ChunkMeshMode::Greedy => mesh_chunk_plane_greedy(vis, chunk, face, layer, dest),
+        });
+    }
+
+    /// Meshes like [`Self::build`], also recording the tile of each face.
+    pub fn build_tiled<T: FaceVisibilityProvider + FaceTileProvider>(&mut self, vis: &T) {
+        let mode = self.mode;
+        self.build_with(T::VERTEX_LAYOUT, true, |chunk, face, layer, dest, dest_tiles| match mode {
+            ChunkMeshMode::Culled => mesh_chunk_plane_tiled(vis, chunk, face, layer, dest, dest_tiles),
+            ChunkMeshMode::Greedy => mesh_chunk_plane_greedy_tiled(vis, chunk, face, layer, dest, dest_tiles),
+        });
+    }
+
+    /// Meshes like [`Self::build_tiled`], also packing the ambient occlusion
+    /// of each face, see [`super::with_vertex_ao`].
+    pub fn build_ao<T: FaceVisibilityProvider + FaceTileProvider + FaceOcclusionProvider>(&mut self, vis: &T) {
+        let mode = self.mode;
+        self.build_with(T::VERTEX_LAYOUT, true, |chunk, face, layer, dest, dest_tiles| match mode {
+            ChunkMeshMode::Culled => mesh_chunk_plane_ao(vis, chunk, face, layer, dest, dest_tiles),
+            ChunkMeshMode::Greedy => mesh_chunk_plane_greedy_ao(vis, chunk, face, layer, dest, dest_tiles),
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.faces.clear();
+        self.tiles.clear();
+        self.ranges = Default::default();
+    }
+
+    /// Encoded faces of every direction, see [`Self::layout`].
+    #[must_use]
+    pub fn faces(&self) -> &[u32] {
+        &self.faces
+    }
+
+    /// Layout of [`Self::faces`], set by the chunk size of the last build.
+    #[must_use]
+    pub const fn layout(&self) -> VertexLayout {
+        self.layout
+    }
+
+    /// Tile of each face, empty when built with [`Self::build`].
+    #[must_use]
+    pub fn tiles(&self) -> &[TileIdentifier] {
+        &self.tiles
+    }
+
+    /// Range of [`Self::faces`] holding the faces pointing along `face`.
+    #[must_use]
+    pub fn face_range(&self, face: VisFace) -> Range<usize> {
+        self.ranges[face as usize].clone()
+    }
+
+    #[must_use]
+    pub fn faces_for(&self, face: VisFace) -> &[u32] {
+        &self.faces[self.face_range(face)]
+    }
+
+    #[must_use]
+    pub fn tiles_for(&self, face: VisFace) -> &[TileIdentifier] {
+        self.tiles.get(self.face_range(face)).unwrap_or_default()
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    fn build_with(
+        &mut self,
+        layout:         VertexLayout,
+        tiled:          bool,
+        mut mesh_plane: impl FnMut([usize; 3], VisFace, u32, &mut [u32], &mut [TileIdentifier]) -> usize,
+    ) {
+        self.clear();
+        self.layout = layout;
+        let vis_size = layout.chunk_size()/8;
+        for face in VisFace::ALL {
+            let start = self.faces.len();
+            for x in 0..vis_size {
+                for y in 0..vis_size {
+                    for z in 0..vis_size {
+                        mesh_sub_chunk(tiled, [x, y, z], face, &mut self.faces, &mut self.tiles, &mut mesh_plane);
+                    }
+                }
+            }
+            self.ranges[face as usize] = start..self.faces.len();
+        }
+    }
+
+}
+
+#[cfg(feature = "rayon")]
+impl ChunkMesh {
+
+    /// Meshes like [`Self::build`], splitting the chunk into one task per
+    /// sub-chunk and direction on the rayon thread pool.
+    ///
+    /// The faces are stitched back in the same order as [`Self::build`], so
+    /// both produce identical meshes.
+    pub fn par_build<T: FaceVisibilityProvider + Sync>(&mut self, vis: &T) {
+        let mode = self.mode;
+        self.par_build_with(T::VERTEX_LAYOUT, false, |chunk, face, layer, dest, _| match mode {
+            ChunkMeshMode::Culled => mesh_chunk_plane(vis, chunk, face, layer, dest),
+            ChunkMeshMode::Greedy => mesh_chunk_plane_greedy(vis, chunk, face, layer, dest),
+        });
+    }
+
+    /// Parallel version of [`Self::build_tiled`], see [`Self::par_build`].
+    pub fn par_build_tiled<T: FaceVisibilityProvider + FaceTileProvider + Sync>(&mut self, vis: &T) {
+        let mode = self.mode;
+        self.par_build_with(T::VERTEX_LAYOUT, true, |chunk, face, layer, dest, dest_tiles| match mode {
+            ChunkMeshMode::Culled => mesh_chunk_plane_tiled(vis, chunk, face, layer, dest, dest_tiles),
+            ChunkMeshMode::Greedy => mesh_chunk_plane_greedy_tiled(vis, chunk, face, layer, dest, dest_tiles),
+        });
+    }
+
+    /// Parallel version of [`Self::build_ao`], see [`Self::par_build`].
+    pub fn par_build_ao<T: FaceVisibilityProvider + FaceTileProvider + FaceOcclusionProvider + Sync>(&mut self, vis: &T) {
+        let mode = self.mode;
+        self.par_build_with(T::VERTEX_LAYOUT, true, |chunk, face, layer, dest, dest_tiles| match mode {
+            ChunkMeshMode::Culled => mesh_chunk_plane_ao(vis, chunk, face, layer, dest, dest_tiles),
+            ChunkMeshMode::Greedy => mesh_chunk_plane_greedy_ao(vis, chunk, face, layer, dest, dest_tiles),
+        });
+    }
+
+    fn par_build_with(
+        &mut self,
+        layout:     VertexLayout,
+        tiled:      bool,
+        mesh_plane: impl Fn([usize; 3], VisFace, u32, &mut [u32], &mut [TileIdentifier]) -> usize + Sync,
+    ) {
+        use rayon::prelude::*;
+
+        let vis_size   = layout.chunk_size()/8;
+        let vis_length = vis_size*vis_size*vis_size;
+
+        // Tasks are ordered like the loops of `build_with`, and collecting
+        // an indexed iterator keeps that order.
+        let parts: Vec<_> = (0..VisFace::ALL.len()*vis_length).into_par_iter().map(|i| {
+            let face  = VisFace::ALL[i / vis_length];
+            let sub   = i % vis_length;
+            let chunk = [
+                sub / (vis_size*vis_size),
+                (sub / vis_size) % vis_size,
+                sub % vis_size,
+            ];
+
+            let mut faces = Vec::new();
+            let mut face_tiles = Vec::new();
+            mesh_sub_chunk(tiled, chunk, face, &mut faces, &mut face_tiles, &mut &mesh_plane);
+            (faces, face_tiles)
+        }).collect();
+
+        self.clear();
+        self.layout = layout;
+        for (face, parts) in VisFace::ALL.into_iter().zip(parts.chunks(vis_length)) {
+            let start = self.faces.len();
+            for (faces, face_tiles) in parts {
+                self.faces.extend_from_slice(faces);
+                self.tiles.extend_from_slice(face_tiles);
+            }
+            self.ranges[face as usize] = start..self.faces.len();
+        }
+    }
+
+}
+
+/// Builds one mesh per source on the rayon thread pool, returned in the
+/// same order as `sources`.
+///
+/// `build` is usually one of the serial builders, for example meshing every
+/// [`crate::world::ChunkNeighbourhood`] around a player at once with
+/// `par_build_chunk_meshes(&neighbourhoods, mode, ChunkMesh::build_ao)`.
+#[cfg(feature = "rayon")]
+#[must_use]
+pub fn par_build_chunk_meshes<S: Sync>(
+    sources: &[S],
+    mode:    ChunkMeshMode,
+    build:   impl Fn(&mut ChunkMesh, &S) + Sync,
+) -> Vec<ChunkMesh> {
+    use rayon::prelude::*;
+
+    sources.par_iter().map(|source| {
+        let mut mesh = ChunkMesh::new(mode);
+        build(&mut mesh, source);
+        mesh
+    }).collect()
+}
+
+/// Appends every layer of one sub-chunk facing `face` to the buffers.
+fn mesh_sub_chunk(
+    tiled:      bool,
+    chunk:      [usize; 3],
+    face:       VisFace,
+    dest:       &mut Vec<u32>,
+    dest_tiles: &mut Vec<TileIdentifier>,
+    mesh_plane: &mut impl FnMut([usize; 3], VisFace, u32, &mut [u32], &mut [TileIdentifier]) -> usize,
+) {
+    for layer in 0..8 {
+        let size = dest.len();
+        dest.resize(size + 8*8, 0);
+        let plane_tiles = if tiled {
+            dest_tiles.resize(size + 8*8, TileIdentifier::DEFAULT);
+            &mut dest_tiles[size..]
+        } else {
+            &mut []
+        };
+
+        let count = mesh_plane(chunk, face, layer, &mut dest[size..], plane_tiles);
+        dest.truncate(size + count);
+        if tiled {
+            dest_tiles.truncate(size + count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{tiles::{TileDefinition, TileRegistry}, world::{ChunkStorage, PosBlock}};
+
+    use super::*;
+
+    fn storage() -> ChunkStorage<16> {
+        let mut registry = TileRegistry::new();
+        let ids = ["test:stone", "test:dirt"].map(|name| registry.register(name, TileDefinition::new_opaque(name)).unwrap());
+        let mut storage = <ChunkStorage<16>>::new_empty();
+        for idx in 0..16*16*16 {
+            let pos = <PosBlock<16>>::from_idx(idx);
+            if pos.y < 5 + (pos.x / 4) || (pos.x*3 + pos.z*5) % 7 == 0 {
+                storage.update(pos, ids[usize::from(pos.z >= 10)], &registry);
+            }
+        }
+        storage
+    }
+
+    /// Tile of every unit voxel face covered by the faces.
+    fn unit_faces(mesh: &ChunkMesh) -> HashMap<(VisFace, u8, u8, u8), TileIdentifier> {
+        let mut result = HashMap::new();
+        for face in VisFace::ALL {
+            for (i, &vert) in mesh.faces_for(face).iter().enumerate() {
+                let (x, y, layer, vert_face) = mesh.layout().decode(vert);
+                let (width, height) = mesh.layout().decode_size(vert);
+                assert_eq!(vert_face, face);
+                for u in x..x + width {
+                    for v in y..y + height {
+                        assert!(result.insert((face, u, v, layer), mesh.tiles_for(face)[i]).is_none());
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn face_ranges_partition_faces() {
+        let storage = storage();
+        let mut mesh = ChunkMesh::default();
+        mesh.build(&storage);
+        assert!(!mesh.is_empty());
+        assert!(mesh.tiles().is_empty());
+        assert_eq!(mesh.layout(), VertexLayout::of::<16>());
+
+        let mut end = 0;
+        for face in VisFace::ALL {
+            let range = mesh.face_range(face);
+            assert_eq!(range.start, end);
+            end = range.end;
+            for &vert in mesh.faces_for(face) {
+                assert_eq!(mesh.layout().decode(vert).3, face);
+            }
+        }
+        assert_eq!(end, mesh.len());
+
+        mesh.clear();
+        assert!(mesh.is_empty());
+        assert!(VisFace::ALL.into_iter().all(|face| mesh.face_range(face).is_empty()));
+    }
+
+    #[test]
+    fn greedy_covers_culled() {
+        let storage = storage();
+        let mut culled = ChunkMesh::new(ChunkMeshMode::Culled);
+        let mut greedy = ChunkMesh::new(ChunkMeshMode::Greedy);
+        culled.build_tiled(&storage);
+        greedy.build_tiled(&storage);
+
+        assert!(greedy.len() < culled.len());
+        assert_eq!(unit_faces(&greedy), unit_faces(&culled));
+        assert_eq!(greedy.tiles().len(), greedy.len());
+
+        // Rebuilding replaces the faces rather than appending
+        let len = greedy.len();
+        greedy.build_tiled(&storage);
+        assert_eq!(greedy.len(), len);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_build_matches_build() {
+        let storage = storage();
+        for mode in [ChunkMeshMode::Culled, ChunkMeshMode::Greedy] {
+            let (mut serial, mut parallel) = (ChunkMesh::new(mode), ChunkMesh::new(mode));
+            serial.build_tiled(&storage);
+            parallel.par_build_tiled(&storage);
+            assert_eq!(serial.faces(), parallel.faces());
+            assert_eq!(serial.tiles(), parallel.tiles());
+        }
+    }
+
+}
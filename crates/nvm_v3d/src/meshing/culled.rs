@@ -1,12 +1,70 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
-use super::{bit_plane::BitPlane, VisFace, encode_vertex};
+use crate::{tiles::{TileIdentifier, TileOpacity}, world::CHUNK_SIZE};
+
+use super::{bit_plane::BitPlane, VertexLayout, VisFace};
 
 pub trait FaceVisibilityProvider {
+    /// Voxels along each axis of the chunk, which sets the sub-chunks meshed
+    /// and the layout of the encoded faces.
+    const CHUNK_SIZE: usize = CHUNK_SIZE;
+
+    /// Layout of the faces meshed from this provider.
+    const VERTEX_LAYOUT: VertexLayout = VertexLayout::for_chunk_size(Self::CHUNK_SIZE);
+
+    /// Returns the visible faces of opaque tiles.
     fn get_face_visibility_plane(&self, chunk: [usize; 3], face: VisFace, layer: u32) -> BitPlane;
+
+    /// Returns the visible faces of tiles with the given opacity. Providers
+    /// that only track opaque tiles report no other faces.
+    fn get_face_visibility_plane_for(&self, chunk: [usize; 3], face: VisFace, layer: u32, opacity: TileOpacity) -> BitPlane {
+        match opacity {
+            TileOpacity::Opaque => self.get_face_visibility_plane(chunk, face, layer),
+            _ => BitPlane::DEFAULT,
+        }
+    }
+}
+
+/// Presents the faces of a single opacity class as the opaque faces of a
+/// provider, so cutout and translucent tiles can be meshed into their own
+/// lists with any of the meshing functions.
+#[derive(Debug, Clone, Copy)]
+pub struct FaceVisibilityLayer<'a, T> {
+    pub vis:     &'a T,
+    pub opacity: TileOpacity,
+}
+
+impl<'a, T> FaceVisibilityLayer<'a, T> {
+
+    #[must_use]
+    pub const fn new(vis: &'a T, opacity: TileOpacity) -> Self {
+        Self{ vis, opacity }
+    }
+
 }
 
-pub fn mesh_chunk_plane(vis: &impl FaceVisibilityProvider, chunk: [usize; 3], face: VisFace, layer: u32, dest: &mut [u32]) -> usize {
+impl<T: FaceVisibilityProvider> FaceVisibilityProvider for FaceVisibilityLayer<'_, T> {
+    const CHUNK_SIZE: usize = T::CHUNK_SIZE;
+
+    fn get_face_visibility_plane(&self, chunk: [usize; 3], face: VisFace, layer: u32) -> BitPlane {
+        self.vis.get_face_visibility_plane_for(chunk, face, layer, self.opacity)
+    }
+}
+
+impl<T: FaceTileProvider> FaceTileProvider for FaceVisibilityLayer<'_, T> {
+    fn get_face_tile(&self, pos: [usize; 3]) -> TileIdentifier {
+        self.vis.get_face_tile(pos)
+    }
+}
+
+/// Provides the tile that owns each visible face, so meshes can carry a
+/// material per face.
+pub trait FaceTileProvider {
+    /// Returns the tile at the given chunk-local block position.
+    fn get_face_tile(&self, pos: [usize; 3]) -> TileIdentifier;
+}
+
+pub fn mesh_chunk_plane<T: FaceVisibilityProvider>(vis: &T, chunk: [usize; 3], face: VisFace, layer: u32, dest: &mut [u32]) -> usize {
     assert!(dest.len() >= 8*8);
 
     let visibility = vis.get_face_visibility_plane(chunk, face, layer).to_raw();
@@ -21,7 +79,7 @@ pub fn mesh_chunk_plane(vis: &impl FaceVisibilityProvider, chunk: [usize; 3], fa
             let k = i+j;
             let x = k & 0x07;
             let y = k >> 3;
-            dest[size] = encode_vertex(
+            dest[size] = T::VERTEX_LAYOUT.encode(
                 offset[0] +     x as u8, 
                 offset[1] +     y as u8, 
                 offset[2] + layer as u8,
@@ -38,4 +96,48 @@ pub fn mesh_chunk_plane(vis: &impl FaceVisibilityProvider, chunk: [usize; 3], fa
     }
 
     size
-}
\ No newline at end of file
+}
+
+/// Meshes a plane like [`mesh_chunk_plane`], writing the tile of each face to
+/// the matching index of `dest_tiles`.
+pub fn mesh_chunk_plane_tiled<T: FaceVisibilityProvider + FaceTileProvider>(
+    vis:        &T,
+    chunk:      [usize; 3],
+    face:       VisFace,
+    layer:      u32,
+    dest:       &mut [u32],
+    dest_tiles: &mut [TileIdentifier],
+) -> usize {
+    assert!(dest.len() >= 8*8);
+    assert!(dest_tiles.len() >= 8*8);
+
+    let mut visibility = vis.get_face_visibility_plane(chunk, face, layer).to_raw();
+    let offset = face.axis().to_local_usize(chunk).map(|v| (v*8) as u8);
+
+    let mut size = 0;
+    while visibility != 0 {
+        let i = visibility.trailing_zeros();
+        visibility &= visibility - 1;
+        dest[size] = T::VERTEX_LAYOUT.encode(
+            offset[0] + (i & 0x07) as u8,
+            offset[1] + (i >>   3) as u8,
+            offset[2] +      layer as u8,
+            face
+        );
+        dest_tiles[size] = get_face_tile(vis, chunk, face, layer, i);
+        size += 1;
+    }
+
+    size
+}
+
+/// Looks up the tile owning bit `i` of a face visibility plane.
+pub(crate) fn get_face_tile(tiles: &impl FaceTileProvider, chunk: [usize; 3], face: VisFace, layer: u32, i: u32) -> TileIdentifier {
+    let local = [(i & 0x07) as usize, (i >> 3) as usize, layer as usize];
+    let world = face.axis().to_world_usize(local);
+    tiles.get_face_tile([
+        chunk[0]*8 + world[0],
+        chunk[1]*8 + world[1],
+        chunk[2]*8 + world[2],
+    ])
+}
@@ -0,0 +1,168 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use crate::tiles::TileIdentifier;
+
+use super::{FaceOcclusionProvider, FaceTileProvider, FaceVisibilityProvider, VisFace, get_face_ao, get_face_tile};
+
+/// Meshes a plane like [`super::mesh_chunk_plane`], but merges adjacent
+/// visible faces into rectangles. Each rectangle is grown along x first, then
+/// extended along y while the rows below match.
+pub fn mesh_chunk_plane_greedy<T: FaceVisibilityProvider>(vis: &T, chunk: [usize; 3], face: VisFace, layer: u32, dest: &mut [u32]) -> usize {
+    assert!(dest.len() >= 8*8);
+
+    let mut remaining = vis.get_face_visibility_plane(chunk, face, layer).to_raw();
+    let offset = face.axis().to_local_usize(chunk).map(|v| (v*8) as u8);
+
+    let mut size = 0;
+    while remaining != 0 {
+        let (x, y, width, height, rect) = take_greedy_rect(remaining);
+        remaining &= !rect;
+
+        dest[size] = T::VERTEX_LAYOUT.encode_sized(
+            offset[0] +     x as u8,
+            offset[1] +     y as u8,
+            offset[2] + layer as u8,
+            face,
+            width  as u8,
+            height as u8,
+        );
+        size += 1;
+    }
+
+    size
+}
+
+/// Meshes a plane like [`mesh_chunk_plane_greedy`], writing the tile of each
+/// face to the matching index of `dest_tiles`. Only faces of the same tile
+/// are merged.
+pub fn mesh_chunk_plane_greedy_tiled<T: FaceVisibilityProvider + FaceTileProvider>(
+    vis:        &T,
+    chunk:      [usize; 3],
+    face:       VisFace,
+    layer:      u32,
+    dest:       &mut [u32],
+    dest_tiles: &mut [TileIdentifier],
+) -> usize {
+    assert!(dest.len() >= 8*8);
+    assert!(dest_tiles.len() >= 8*8);
+
+    let visibility = vis.get_face_visibility_plane(chunk, face, layer).to_raw();
+
+    let mut keys = [0; 8*8];
+    let mut bits = visibility;
+    while bits != 0 {
+        let i = bits.trailing_zeros();
+        bits &= bits - 1;
+        keys[i as usize] = u32::from(get_face_tile(vis, chunk, face, layer, i).to_raw());
+    }
+
+    mesh_plane_greedy_keyed::<T>(visibility, &keys, chunk, face, layer, dest, |_, idx, key| {
+        dest_tiles[idx] = TileIdentifier::from_raw(key as u16);
+    })
+}
+
+/// Meshes a plane like [`mesh_chunk_plane_greedy_tiled`], also packing the
+/// ambient occlusion of each face, see [`super::mesh_chunk_plane_ao`]. Only
+/// faces of the same tile and occlusion are merged.
+pub fn mesh_chunk_plane_greedy_ao<T: FaceVisibilityProvider + FaceTileProvider + FaceOcclusionProvider>(
+    vis:        &T,
+    chunk:      [usize; 3],
+    face:       VisFace,
+    layer:      u32,
+    dest:       &mut [u32],
+    dest_tiles: &mut [TileIdentifier],
+) -> usize {
+    const { assert!(T::VERTEX_LAYOUT.has_ao(), "Ambient occlusion doesn't fit the vertex layout of the chunk size") };
+    assert!(dest.len() >= 8*8);
+    assert!(dest_tiles.len() >= 8*8);
+
+    let visibility = vis.get_face_visibility_plane(chunk, face, layer).to_raw();
+
+    let mut keys = [0; 8*8];
+    let mut bits = visibility;
+    while bits != 0 {
+        let i = bits.trailing_zeros();
+        bits &= bits - 1;
+        let tile = get_face_tile(vis, chunk, face, layer, i).to_raw();
+        let ao   = get_face_ao(vis, chunk, face, layer, i);
+        keys[i as usize] = u32::from(tile) | (u32::from(ao) << 16);
+    }
+
+    mesh_plane_greedy_keyed::<T>(visibility, &keys, chunk, face, layer, dest, |vert, idx, key| {
+        *vert = T::VERTEX_LAYOUT.with_ao(*vert, (key >> 16) as u8);
+        dest_tiles[idx] = TileIdentifier::from_raw(key as u16);
+    })
+}
+
+/// Greedily merges the visible faces of a plane, only joining faces with
+/// equal keys and encoding them in the layout of `T`. Calls `emit` with each
+/// written face, its index and its key.
+fn mesh_plane_greedy_keyed<T: FaceVisibilityProvider>(
+    visibility: u64,
+    keys:       &[u32; 8*8],
+    chunk:      [usize; 3],
+    face:       VisFace,
+    layer:      u32,
+    dest:       &mut [u32],
+    mut emit:   impl FnMut(&mut u32, usize, u32),
+) -> usize {
+    let offset = face.axis().to_local_usize(chunk).map(|v| (v*8) as u8);
+
+    let mut size = 0;
+    let mut remaining = visibility;
+    while remaining != 0 {
+        let key = keys[remaining.trailing_zeros() as usize];
+
+        let mut matching = 0;
+        let mut bits = remaining;
+        while bits != 0 {
+            let i = bits.trailing_zeros();
+            bits &= bits - 1;
+            if keys[i as usize] == key {
+                matching |= 1 << i;
+            }
+        }
+
+        let (x, y, width, height, rect) = take_greedy_rect(matching);
+        remaining &= !rect;
+
+        dest[size] = T::VERTEX_LAYOUT.encode_sized(
+            offset[0] +     x as u8,
+            offset[1] +     y as u8,
+            offset[2] + layer as u8,
+            face,
+            width  as u8,
+            height as u8,
+        );
+        emit(&mut dest[size], size, key);
+        size += 1;
+    }
+
+    size
+}
+
+/// Finds the rectangle starting at the lowest set bit of `mask`, returning
+/// its position, size and the bits it covers.
+const fn take_greedy_rect(mask: u64) -> (u32, u32, u32, u32, u64) {
+    let i = mask.trailing_zeros();
+    let x = i & 0x07;
+    let y = i >> 3;
+
+    let row      = (mask >> (y*8)) & 0xFF;
+    let width    = (row >> x).trailing_ones();
+    let row_mask = ((1_u64 << width) - 1) << x;
+
+    let mut height = 1;
+    while y + height < 8 && ((mask >> ((y + height)*8)) & row_mask) == row_mask {
+        height += 1;
+    }
+
+    let mut rect = 0;
+    let mut j = y;
+    while j < y + height {
+        rect |= row_mask << (j*8);
+        j += 1;
+    }
+
+    (x, y, width, height, rect)
+}
@@ -0,0 +1,176 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use bytemuck::{Pod, Zeroable};
+use glam::IVec3;
+
+use crate::tiles::TileIdentifier;
+
+use super::{ChunkMesh, VertexLayout, create_quad_corners_sized, get_face_basis};
+
+/// Indices of the two triangles of every quad in an [`IndexedMesh`], relative
+/// to the quad's first vertex.
+pub const QUAD_INDICES: [u32; 6] = [0, 1, 2, 3, 2, 1];
+
+/// One corner of a face in an [`IndexedMesh`].
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct IndexedVertex {
+    /// Chunk-local position.
+    pub position: [f32; 3],
+    /// Unit normal pointing out of the face.
+    pub normal:   [f32; 3],
+    /// Texture coordinate in voxels, repeating across merged faces.
+    pub uv:       [f32; 2],
+    /// Ambient occlusion of the corner, see [`super::with_vertex_ao`].
+    pub ao:       u32,
+    /// Raw [`TileIdentifier`] of the face.
+    pub tile:     u32,
+}
+
+/// Conventional vertex buffer built from encoded faces, four vertices per
+/// face. Every quad shares the same [`QUAD_INDICES`], so a single buffer from
+/// [`create_quad_indices`] can draw any mesh with at most as many quads.
+///
+/// Rebuilding a mesh reuses its buffer, like [`ChunkMesh`].
+#[derive(Debug, Default, Clone)]
+pub struct IndexedMesh {
+    vertices: Vec<IndexedVertex>,
+}
+
+impl IndexedMesh {
+
+    /// Replaces the current mesh with the faces and tiles of a chunk mesh.
+    pub fn build(&mut self, mesh: &ChunkMesh) {
+        self.clear();
+        self.add_faces_with_layout(mesh.layout(), mesh.faces(), mesh.tiles());
+    }
+
+    /// Adds faces encoded with [`super::encode_vertex_sized`] and the tile of
+    /// each face. Tiles may be empty for untiled meshes, leaving them `0`.
+    pub fn add_faces(&mut self, faces: &[u32], tiles: &[TileIdentifier]) {
+        self.add_faces_with_layout(VertexLayout::DEFAULT, faces, tiles);
+    }
+
+    /// Adds faces like [`Self::add_faces`], encoded with the given layout.
+    pub fn add_faces_with_layout(&mut self, layout: VertexLayout, faces: &[u32], tiles: &[TileIdentifier]) {
+        self.vertices.reserve(faces.len()*4);
+        for (i, &vert) in faces.iter().enumerate() {
+            let tile = tiles.get(i).map_or(0, |v| u32::from(v.to_raw()));
+            self.add_face(layout, vert, tile);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    #[must_use]
+    pub fn vertices(&self) -> &[IndexedVertex] {
+        &self.vertices
+    }
+
+    /// Number of quads.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// Number of indices needed to draw every quad.
+    #[must_use]
+    pub const fn index_count(&self) -> usize {
+        self.len()*QUAD_INDICES.len()
+    }
+
+    fn add_face(&mut self, layout: VertexLayout, vert: u32, tile: u32) {
+        let (x, y, layer, face) = layout.decode(vert);
+        let (width, height) = layout.decode_size(vert);
+        let corners = create_quad_corners_sized(x, y, layer, face, width, height);
+        let ao      = layout.decode_ao(vert);
+        let basis   = get_face_basis(face);
+        let normal  = (-basis[2]).as_vec3().to_array();
+
+        // Orders the corners so the shared diagonal of QUAD_INDICES is the
+        // brighter one, matching the split of the chunk shader
+        let flip  = ao[0] + ao[3] > ao[1] + ao[2];
+        let order = match (basis[2].dot(IVec3::ONE) < 0, flip) {
+            (false, false) => [0, 2, 1, 3],
+            (false, true)  => [1, 0, 3, 2],
+            (true,  false) => [0, 1, 2, 3],
+            (true,  true)  => [2, 0, 3, 1],
+        };
+
+        self.vertices.extend(order.map(|c| IndexedVertex{
+            position: corners[c].as_vec3().to_array(),
+            normal,
+            uv:       [((c & 1) as u8*width) as f32, ((c >> 1) as u8*height) as f32],
+            ao:       u32::from(ao[c]),
+            tile,
+        }));
+    }
+
+}
+
+/// Creates an index buffer drawing up to `quads` quads of an [`IndexedMesh`].
+#[must_use]
+pub fn create_quad_indices(quads: usize) -> Vec<u32> {
+    (0..quads as u32).flat_map(|i| QUAD_INDICES.map(|v| i*4 + v)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::meshing::{VisFace, create_quad_for_vertex_sized};
+
+    use super::*;
+
+    fn triangle_normal(p: [Vec3; 3]) -> Vec3 {
+        (p[1] - p[0]).cross(p[2] - p[0]).normalize()
+    }
+
+    #[test]
+    fn quads_face_outwards() {
+        let layout = VertexLayout::of::<32>();
+        for face in VisFace::ALL {
+            let normal = (-get_face_basis(face)[2]).as_vec3();
+            let reference = create_quad_for_vertex_sized(3, 4, 5, face, 2, 3).map(|v| v.as_vec3());
+            assert_eq!(triangle_normal([reference[0], reference[1], reference[2]]), normal, "{face:?}");
+            assert_eq!(triangle_normal([reference[3], reference[4], reference[5]]), normal, "{face:?}");
+
+            // Unoccluded, then brighter along the 0-3 diagonal, flipping the split, then along 1-2
+            for ao in [0xFF, 0b11_00_00_11, 0b00_11_11_00] {
+                let mut mesh = IndexedMesh::default();
+                mesh.add_faces_with_layout(layout, &[layout.with_ao(layout.encode_sized(3, 4, 5, face, 2, 3), ao)], &[]);
+                let vertices = mesh.vertices();
+                assert_eq!(vertices.len(), 4);
+
+                let indices = create_quad_indices(mesh.len());
+                assert_eq!(indices.len(), mesh.index_count());
+                for triangle in indices.chunks(3) {
+                    let positions = [0, 1, 2].map(|i| Vec3::from(vertices[triangle[i] as usize].position));
+                    assert_eq!(triangle_normal(positions), normal, "{face:?} {ao:#x}");
+                }
+                for vertex in vertices {
+                    assert_eq!(Vec3::from(vertex.normal), normal);
+                }
+
+                let mut corners: Vec<_> = vertices.iter().map(|v| v.position.map(f32::to_bits)).collect();
+                let mut expected: Vec<_> = reference.iter().map(|v| v.to_array().map(f32::to_bits)).collect();
+                corners.sort_unstable();
+                expected.sort_unstable();
+                expected.dedup();
+                assert_eq!(corners, expected);
+
+                // The shared diagonal is the brighter one
+                let shared = [vertices[QUAD_INDICES[1] as usize].ao, vertices[QUAD_INDICES[2] as usize].ao];
+                assert_eq!(shared, [3, 3], "{face:?} {ao:#x}");
+            }
+        }
+    }
+
+}
@@ -1,14 +1,37 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
+use glam::IVec3;
+
 mod bit_plane;
 pub use bit_plane::*;
 
 mod culled;
 pub use culled::*;
 
+mod greedy;
+pub use greedy::*;
+
+mod ao;
+pub use ao::*;
+
 mod vertex;
 pub use vertex::*;
 
+mod chunk_mesh;
+pub use chunk_mesh::*;
+
+mod validate;
+pub use validate::*;
+
+mod indexed;
+pub use indexed::*;
+
+mod shape;
+pub use shape::*;
+
+mod surface_nets;
+pub use surface_nets::*;
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum VisAxis {
@@ -58,8 +81,9 @@ impl VisAxis {
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum VisFace {
+    #[default]
     PosX = 0,
     PosY = 1,
     PosZ = 2,
@@ -70,6 +94,32 @@ pub enum VisFace {
 
 impl VisFace {
 
+    pub const ALL: [Self; 6] = [
+        Self::PosX,
+        Self::PosY,
+        Self::PosZ,
+        Self::NegX,
+        Self::NegY,
+        Self::NegZ,
+    ];
+
+    /// Pairs of faces on different axes, each pointing towards one of the
+    /// twelve edges of a voxel.
+    pub const EDGES: [[Self; 2]; 12] = [
+        [Self::PosX, Self::PosY], [Self::PosX, Self::NegY], [Self::NegX, Self::PosY], [Self::NegX, Self::NegY],
+        [Self::PosX, Self::PosZ], [Self::PosX, Self::NegZ], [Self::NegX, Self::PosZ], [Self::NegX, Self::NegZ],
+        [Self::PosY, Self::PosZ], [Self::PosY, Self::NegZ], [Self::NegY, Self::PosZ], [Self::NegY, Self::NegZ],
+    ];
+
+    /// Triples of faces on the X, Y and Z axes, each pointing towards one of
+    /// the eight corners of a voxel.
+    pub const CORNERS: [[Self; 3]; 8] = [
+        [Self::PosX, Self::PosY, Self::PosZ], [Self::NegX, Self::PosY, Self::PosZ],
+        [Self::PosX, Self::NegY, Self::PosZ], [Self::NegX, Self::NegY, Self::PosZ],
+        [Self::PosX, Self::PosY, Self::NegZ], [Self::NegX, Self::PosY, Self::NegZ],
+        [Self::PosX, Self::NegY, Self::NegZ], [Self::NegX, Self::NegY, Self::NegZ],
+    ];
+
     #[must_use]
     pub const fn from_raw(raw: u16) -> Option<Self> {
         match raw {
@@ -107,4 +157,22 @@ impl VisFace {
         }
     }
 
+    /// The unit offset towards the neighbour on this face, `PosX` being `+x`
+    /// like the neighbours of [`crate::world::ChunkNeighbourhood`].
+    ///
+    /// The face meshers name faces the other way around: a `PosX` quad lies
+    /// on the voxel's `-x` side and is culled by the voxel at `x - 1`, the
+    /// neighbour on [`Self::reverse`].
+    #[must_use]
+    pub const fn offset(self) -> IVec3 {
+        match self {
+            VisFace::PosX => IVec3::X,
+            VisFace::PosY => IVec3::Y,
+            VisFace::PosZ => IVec3::Z,
+            VisFace::NegX => IVec3::NEG_X,
+            VisFace::NegY => IVec3::NEG_Y,
+            VisFace::NegZ => IVec3::NEG_Z,
+        }
+    }
+
 }
\ No newline at end of file
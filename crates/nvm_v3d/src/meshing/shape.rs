@@ -0,0 +1,382 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use glam::{IVec3, Vec3};
+
+use crate::tiles::{ShapeBox, TileIdentifier, TileRegistry, TileShape};
+
+use super::{FaceOcclusionProvider, FaceTileProvider, FaceVisibilityProvider, VertexLayout, VisFace, get_face_basis};
+
+/// Face of a shape quad, either a side of a [`ShapeBox`] named like the cube
+/// faces or one of the two diagonals of a [`TileShape::Cross`].
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShapeFace {
+    PosX         = 0,
+    PosY         = 1,
+    PosZ         = 2,
+    NegX         = 3,
+    NegY         = 4,
+    NegZ         = 5,
+    /// Vertical quad from the `(0, 0)` to the `(1, 1)` corner on `x`, `z`.
+    Diagonal     = 6,
+    /// Vertical quad from the `(0, 1)` to the `(1, 0)` corner on `x`, `z`.
+    AntiDiagonal = 7,
+}
+
+impl ShapeFace {
+
+    #[must_use]
+    pub const fn from_raw(raw: u16) -> Option<Self> {
+        match raw {
+            0 => Some(Self::PosX),
+            1 => Some(Self::PosY),
+            2 => Some(Self::PosZ),
+            3 => Some(Self::NegX),
+            4 => Some(Self::NegY),
+            5 => Some(Self::NegZ),
+            6 => Some(Self::Diagonal),
+            7 => Some(Self::AntiDiagonal),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn from_vis_face(face: VisFace) -> Self {
+        match face {
+            VisFace::PosX => Self::PosX,
+            VisFace::PosY => Self::PosY,
+            VisFace::PosZ => Self::PosZ,
+            VisFace::NegX => Self::NegX,
+            VisFace::NegY => Self::NegY,
+            VisFace::NegZ => Self::NegZ,
+        }
+    }
+
+    #[must_use]
+    pub const fn to_vis_face(self) -> Option<VisFace> {
+        VisFace::from_raw(self as u16)
+    }
+
+}
+
+impl VertexLayout {
+
+    /// Encodes one quad of a shaped voxel. Quads are drawn separately from
+    /// the cube faces, with box sides at half-voxel precision:
+    ///
+    /// ```text
+    ///  0..b     x
+    ///  b..2b    y
+    /// 2b..3b    z
+    /// 3b..3b+3  face
+    /// 3b+3..+3  box min,     one bit per axis
+    /// 3b+6..+3  box max - 1, one bit per axis
+    /// 3b+9      back side, for the double-sided diagonals
+    /// ```
+    #[must_use]
+    pub const fn encode_shape(
+        self,
+        x:      u8,
+        y:      u8,
+        z:      u8,
+        face:   ShapeFace,
+        bounds: ShapeBox,
+        back:   bool,
+    ) -> u32 {
+        let bits = self.coord_bits();
+        let mask = self.coord_mask();
+        ((        x as u32) & mask)                 |
+        ((        y as u32) & mask) <<  bits        |
+        ((        z as u32) & mask) << (2*bits)     |
+        (      face  as u32)        << (3*bits)     |
+        (( bounds.min[0]      & 0x01) as u32) << (3*bits + 3) |
+        (( bounds.min[1]      & 0x01) as u32) << (3*bits + 4) |
+        (( bounds.min[2]      & 0x01) as u32) << (3*bits + 5) |
+        (((bounds.max[0] - 1) & 0x01) as u32) << (3*bits + 6) |
+        (((bounds.max[1] - 1) & 0x01) as u32) << (3*bits + 7) |
+        (((bounds.max[2] - 1) & 0x01) as u32) << (3*bits + 8) |
+        (      back  as u32)        << (3*bits + 9)
+    }
+
+    #[must_use]
+    pub const fn decode_shape(self, vert: u32) -> (
+        /*x:       */ u8,
+        /*y:       */ u8,
+        /*z:       */ u8,
+        /*face:    */ ShapeFace,
+        /*bounds:  */ ShapeBox,
+        /*back:    */ bool,
+    ) {
+        let bits = self.coord_bits();
+        let mask = self.coord_mask();
+        (
+            ( vert              & mask) as u8,
+            ((vert >>    bits)  & mask) as u8,
+            ((vert >> (2*bits)) & mask) as u8,
+            ShapeFace::from_raw(((vert >> (3*bits)) & 0x07) as u16).unwrap(),
+            ShapeBox::new(
+                [
+                    ((vert >> (3*bits + 3)) & 0x01) as u8,
+                    ((vert >> (3*bits + 4)) & 0x01) as u8,
+                    ((vert >> (3*bits + 5)) & 0x01) as u8,
+                ],
+                [
+                    ((vert >> (3*bits + 6)) & 0x01) as u8 + 1,
+                    ((vert >> (3*bits + 7)) & 0x01) as u8 + 1,
+                    ((vert >> (3*bits + 8)) & 0x01) as u8 + 1,
+                ],
+            ),
+            (vert >> (3*bits + 9)) & 0x01 != 0,
+        )
+    }
+
+}
+
+/// Encodes one quad of a shaped voxel, see [`VertexLayout::encode_shape`].
+/// Uses the [`VertexLayout::DEFAULT`] layout:
+///
+/// ```text
+///  0..5   x
+///  5..10  y
+/// 10..15  z
+/// 15..18  face
+/// 18..21  box min,     one bit per axis
+/// 21..24  box max - 1, one bit per axis
+/// 24      back side, for the double-sided diagonals
+/// ```
+#[must_use]
+pub const fn encode_shape_vertex(
+    x:      u8,
+    y:      u8,
+    z:      u8,
+    face:   ShapeFace,
+    bounds: ShapeBox,
+    back:   bool,
+) -> u32 {
+    VertexLayout::DEFAULT.encode_shape(x, y, z, face, bounds, back)
+}
+
+#[must_use]
+pub const fn decode_shape_vertex(vert: u32) -> (
+    /*x:       */ u8,
+    /*y:       */ u8,
+    /*z:       */ u8,
+    /*face:    */ ShapeFace,
+    /*bounds:  */ ShapeBox,
+    /*back:    */ bool,
+) {
+    VertexLayout::DEFAULT.decode_shape(vert)
+}
+
+/// Creates the two triangles of a shape quad, see
+/// [`VertexLayout::create_quad_for_shape`].
+#[must_use]
+pub fn create_quad_for_shape_vertex(vert: u32) -> [Vec3; 6] {
+    VertexLayout::DEFAULT.create_quad_for_shape(vert)
+}
+
+impl VertexLayout {
+
+    /// Creates the two triangles of a shape quad encoded with
+    /// [`Self::encode_shape`], in voxels and wound like the cube faces.
+    #[must_use]
+    pub fn create_quad_for_shape(self, vert: u32) -> [Vec3; 6] {
+        let (x, y, z, face, bounds, back) = self.decode_shape(vert);
+        let min  = IVec3::from_array(bounds.min.map(i32::from));
+        let max  = IVec3::from_array(bounds.max.map(i32::from));
+        let size = max - min;
+
+        // Corner, edges and winding in half voxels, wound like the cube faces
+        let (base, u, v, positive) = match face.to_vis_face() {
+            Some(face) => {
+                let basis = get_face_basis(face);
+                let start = |axis: IVec3| if axis.dot(IVec3::ONE) < 0 { max } else { min };
+                let base  = start(basis[0]).dot(basis[0].abs())*basis[0].abs()
+                          + start(basis[1]).dot(basis[1].abs())*basis[1].abs()
+                          + start( basis[2]).dot(basis[2].abs())*basis[2].abs();
+                (base, basis[0]*size, basis[1]*size, basis[2].dot(IVec3::ONE) > 0)
+            },
+            None if face == ShapeFace::Diagonal => (min, IVec3::new(size.x, 0, size.z), IVec3::new(0, size.y, 0), !back),
+            None => (IVec3::new(min.x, min.y, max.z), IVec3::new(size.x, 0, -size.z), IVec3::new(0, size.y, 0), !back),
+        };
+
+        let offset = Vec3::new(f32::from(x), f32::from(y), f32::from(z));
+        let p = [base, base + u, base + v, base + u + v].map(|p| offset + p.as_vec3()*0.5);
+
+        if positive {
+            [
+                p[0], p[2], p[1],
+                p[3], p[1], p[2],
+            ]
+        } else {
+            [
+                p[0], p[1], p[2],
+                p[3], p[2], p[1],
+            ]
+        }
+    }
+
+}
+
+/// Meshes every voxel of the chunk with a shape other than [`TileShape::Cube`],
+/// appending one [`VertexLayout::encode_shape`] quad in the layout of `T` and
+/// its tile per visible face.
+///
+/// Box sides on the voxel border are culled against opaque neighbours, using
+/// the [`crate::tiles::TileShapeState::occluded_faces`] of shaped neighbours
+/// within the chunk and [`FaceOcclusionProvider`] outside of it. Cube faces
+/// against covered sides are culled by the provider's visibility planes, see
+/// [`TileRegistry::get_shape_occlusion`].
+pub fn mesh_chunk_shapes<T: FaceVisibilityProvider + FaceTileProvider + FaceOcclusionProvider>(
+    tiles:      &T,
+    registry:   &TileRegistry,
+    dest:       &mut Vec<u32>,
+    dest_tiles: &mut Vec<TileIdentifier>,
+) {
+    for x in 0..T::CHUNK_SIZE {
+        for y in 0..T::CHUNK_SIZE {
+            for z in 0..T::CHUNK_SIZE {
+                let tile  = tiles.get_face_tile([x, y, z]);
+                let shape = registry.get_shape(tile);
+                let (px, py, pz) = (x as u8, y as u8, z as u8);
+
+                if shape.shape == TileShape::Cross {
+                    for face in [ShapeFace::Diagonal, ShapeFace::AntiDiagonal] {
+                        for back in [false, true] {
+                            dest.push(T::VERTEX_LAYOUT.encode_shape(px, py, pz, face, ShapeBox::FULL, back));
+                            dest_tiles.push(tile);
+                        }
+                    }
+                    continue;
+                }
+
+                if shape.shape == TileShape::Cube {
+                    continue;
+                }
+
+                let boxes = shape.boxes();
+                for (i, &bounds) in boxes.iter().enumerate() {
+                    if bounds.is_empty() {
+                        continue;
+                    }
+
+                    for face in VisFace::ALL {
+                        let hidden = if bounds.touches_border(face) {
+                            is_shape_face_culled(tiles, registry, [x, y, z], face)
+                        } else {
+                            is_box_side_covered(boxes[1 - i], bounds, face)
+                        };
+
+                        if !hidden {
+                            dest.push(T::VERTEX_LAYOUT.encode_shape(px, py, pz, ShapeFace::from_vis_face(face), bounds, false));
+                            dest_tiles.push(tile);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether the neighbour against the given voxel face covers it entirely.
+fn is_shape_face_culled<T: FaceVisibilityProvider + FaceTileProvider + FaceOcclusionProvider>(tiles: &T, registry: &TileRegistry, pos: [usize; 3], face: VisFace) -> bool {
+    let front = IVec3::new(pos[0] as i32, pos[1] as i32, pos[2] as i32) - get_face_basis(face)[2];
+    let range = 0..(T::CHUNK_SIZE as i32);
+    if !front.to_array().iter().all(|v| range.contains(v)) {
+        return tiles.is_face_occluder([front.x as isize, front.y as isize, front.z as isize]);
+    }
+
+    let neighbour = tiles.get_face_tile([front.x as usize, front.y as usize, front.z as usize]);
+    registry.is_opaque(neighbour) && registry.get_shape(neighbour).occludes(face.reverse())
+}
+
+/// Whether `other` has the opposite side on the same plane as the given side
+/// of `bounds`, covering it.
+const fn is_box_side_covered(other: ShapeBox, bounds: ShapeBox, face: VisFace) -> bool {
+    let axis = face.axis() as usize;
+    let (plane, other_plane) = match face {
+        VisFace::PosX | VisFace::PosY | VisFace::PosZ => (bounds.min[axis], other.max[axis]),
+        VisFace::NegX | VisFace::NegY | VisFace::NegZ => (bounds.max[axis], other.min[axis]),
+    };
+
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    !other.is_empty() && plane == other_plane
+        && other.min[u] <= bounds.min[u] && bounds.max[u] <= other.max[u]
+        && other.min[v] <= bounds.min[v] && bounds.max[v] <= other.max[v]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{meshing::ChunkMesh, tiles::{TileDefinition, TileHalf, TileProperty, TILE_PROPERTY_HALF}, world::{ChunkIdentifierMode, ChunkStorage, PosBlock}};
+
+    use super::*;
+
+    fn registry() -> (TileRegistry, TileIdentifier, TileIdentifier) {
+        let mut registry = TileRegistry::new();
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+        let slab  = registry.register("test:slab", TileDefinition::new_opaque("Slab")
+            .with_shape(TileShape::Slab)
+            .with_property(TileProperty::of::<TileHalf>(TILE_PROPERTY_HALF))
+        ).unwrap();
+        (registry, stone, slab)
+    }
+
+    #[test]
+    fn shape_vertex_round_trip() {
+        let bounds = [ShapeBox::FULL, ShapeBox::new([0, 0, 1], [2, 1, 2]), ShapeBox::new([1, 1, 0], [2, 2, 1])];
+        for size in [8, 16, 32, 64] {
+            let layout = VertexLayout::for_chunk_size(size);
+            let max    = (size - 1) as u8;
+            for face in (0..8).filter_map(ShapeFace::from_raw) {
+                for bounds in bounds {
+                    for back in [false, true] {
+                        for [x, y, z] in [[0, 0, 0], [max, 1, max/2], [3, max, max]] {
+                            let vert = layout.encode_shape(x, y, z, face, bounds, back);
+                            assert_eq!(layout.decode_shape(vert), (x, y, z, face, bounds, back), "{size} {vert:#x}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mesh_uses_chunk_layout() {
+        let (registry, _, slab) = registry();
+        let mut storage = <ChunkStorage<64>>::new_empty();
+        storage.update(PosBlock::new(40, 50, 60), slab, &registry);
+
+        let (mut faces, mut tiles) = (Vec::new(), Vec::new());
+        mesh_chunk_shapes(&storage, &registry, &mut faces, &mut tiles);
+        assert_eq!(faces.len(), 6);
+        assert_eq!(tiles, [slab; 6]);
+        for vert in faces {
+            let (x, y, z, ..) = VertexLayout::of::<64>().decode_shape(vert);
+            assert_eq!([x, y, z], [40, 50, 60]);
+        }
+    }
+
+    #[test]
+    fn shape_sides_cull_cube_faces() {
+        let (registry, stone, slab) = registry();
+        let top = registry.with_property(slab, TILE_PROPERTY_HALF, TileHalf::Top as u8).unwrap();
+        let cube = <PosBlock<16>>::new(4, 4, 4);
+
+        // A bottom slab above the cube covers its top face, a top slab doesn't
+        for (shape, faces) in [(slab, 5), (top, 6)] {
+            let mut updated = <ChunkStorage<16>>::new_empty();
+            updated.update(cube, stone, &registry);
+            updated.update(PosBlock::new(4, 5, 4), shape, &registry);
+
+            let identifiers: Vec<_> = (0..16*16*16).map(|idx| updated.get(PosBlock::from_idx(idx))).collect();
+            let bulk = ChunkStorage::<16>::from_identifiers(&identifiers, ChunkIdentifierMode::Palette, &registry);
+
+            for storage in [&updated, &bulk] {
+                let mut mesh = ChunkMesh::default();
+                mesh.build(storage);
+                assert_eq!(mesh.faces().len(), faces, "{shape:?}");
+            }
+        }
+    }
+
+}
@@ -0,0 +1,309 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use glam::Vec3;
+
+use crate::world::CHUNK_SIZE;
+
+use super::FaceOcclusionProvider;
+
+/// Provides the density field meshed by [`SmoothMesh`], positive inside and
+/// negative outside of the surface.
+pub trait DensityProvider {
+    /// Returns the density of the voxel at the given chunk-local position.
+    /// The position may lie up to one voxel outside of the chunk.
+    fn get_density(&self, pos: [isize; 3]) -> f32;
+}
+
+impl<F: Fn([isize; 3]) -> f32> DensityProvider for F {
+    fn get_density(&self, pos: [isize; 3]) -> f32 {
+        self(pos)
+    }
+}
+
+/// Presents the occluding voxels of a provider as a density of `1` inside
+/// and `-1` outside, so chunks can be meshed smoothly without a density.
+///
+/// Samples past the chunk come from [`FaceOcclusionProvider::is_face_occluder`].
+/// A [`crate::world::ChunkNeighbourhood`] only holds the face neighbours,
+/// treating samples past an edge or corner of the chunk as unloaded, so the
+/// surface along those edges may not line up with the diagonal chunks.
+#[derive(Debug, Clone, Copy)]
+pub struct SolidDensity<'a, T> {
+    pub solid: &'a T,
+}
+
+impl<'a, T> SolidDensity<'a, T> {
+
+    #[must_use]
+    pub const fn new(solid: &'a T) -> Self {
+        Self{ solid }
+    }
+
+}
+
+impl<T: FaceOcclusionProvider> DensityProvider for SolidDensity<'_, T> {
+    fn get_density(&self, pos: [isize; 3]) -> f32 {
+        if self.solid.is_face_occluder(pos) { 1.0 } else { -1.0 }
+    }
+}
+
+const CELL_NONE: u32 = u32::MAX;
+
+/// Smooth indexed triangle mesh of a chunk, extracted from a density field
+/// with surface nets. Positions are chunk-local, with each voxel's density
+/// sampled at its centre.
+///
+/// Each chunk only emits the quads crossing the edges between its own
+/// voxels and their positive neighbours, so neighbouring chunks meshed with
+/// the same border densities join without gaps or overlaps.
+///
+/// Chunks are `SIZE` voxels across. Rebuilding a mesh reuses its buffers,
+/// like [`super::ChunkMesh`].
+#[derive(Debug, Default, Clone)]
+pub struct SmoothMesh<const SIZE: usize = CHUNK_SIZE> {
+    positions: Vec<[f32; 3]>,
+    normals:   Vec<[f32; 3]>,
+    indices:   Vec<u32>,
+    densities: Vec<f32>,
+    cells:     Vec<u32>,
+}
+
+impl<const SIZE: usize> SmoothMesh<SIZE> {
+
+    /// Samples per axis, one voxel past each side of the chunk.
+    const GRID_SIZE:   usize = SIZE + 2;
+    const GRID_LENGTH: usize = Self::GRID_SIZE*Self::GRID_SIZE*Self::GRID_SIZE;
+
+    /// Meshes the surface of the density field, replacing the current mesh.
+    pub fn build(&mut self, density: &impl DensityProvider) {
+        self.clear();
+        self.sample_densities(density);
+        self.build_vertices();
+        self.build_indices();
+        self.fill_flat_normals();
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.normals.clear();
+        self.indices.clear();
+    }
+
+    #[must_use]
+    pub fn positions(&self) -> &[[f32; 3]] {
+        &self.positions
+    }
+
+    /// Unit normal of each position, pointing out of the surface.
+    #[must_use]
+    pub fn normals(&self) -> &[[f32; 3]] {
+        &self.normals
+    }
+
+    /// Counter-clockwise triangles, seen from outside of the surface.
+    #[must_use]
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn sample_densities(&mut self, density: &impl DensityProvider) {
+        self.densities.clear();
+        self.densities.reserve(Self::GRID_LENGTH);
+        for z in 0..Self::GRID_SIZE {
+            for y in 0..Self::GRID_SIZE {
+                for x in 0..Self::GRID_SIZE {
+                    self.densities.push(density.get_density([x as isize - 1, y as isize - 1, z as isize - 1]));
+                }
+            }
+        }
+    }
+
+    /// Places one vertex in every cell of 8 samples the surface crosses, at
+    /// the mean of the crossings along the cell's edges.
+    fn build_vertices(&mut self) {
+        self.cells.clear();
+        self.cells.resize(Self::GRID_LENGTH, CELL_NONE);
+        for z in 0..Self::GRID_SIZE-1 {
+            for y in 0..Self::GRID_SIZE-1 {
+                for x in 0..Self::GRID_SIZE-1 {
+                    let corners: [f32; 8] = core::array::from_fn(|i| self.densities[Self::grid_idx(x + (i & 1), y + ((i >> 1) & 1), z + (i >> 2))]);
+                    let inside = corners.map(|v| v > 0.0);
+                    if inside.iter().all(|&v| v == inside[0]) {
+                        continue;
+                    }
+
+                    let mut sum   = Vec3::ZERO;
+                    let mut count = 0.0;
+                    for (a, b) in CELL_EDGES {
+                        if inside[a] != inside[b] {
+                            let t = corners[a] / (corners[a] - corners[b]);
+                            sum   += corner_offset(a).lerp(corner_offset(b), t);
+                            count += 1.0;
+                        }
+                    }
+
+                    let mut gradient = Vec3::ZERO;
+                    for (i, &value) in corners.iter().enumerate() {
+                        gradient += (corner_offset(i)*2.0 - Vec3::ONE)*value;
+                    }
+
+                    // Samples sit at voxel centres, a voxel past the grid origin
+                    let position = Vec3::new(x as f32, y as f32, z as f32) - Vec3::splat(0.5) + sum/count;
+                    self.cells[Self::grid_idx(x, y, z)] = self.positions.len() as u32;
+                    self.positions.push(position.to_array());
+                    self.normals.push((-gradient).normalize_or_zero().to_array());
+                }
+            }
+        }
+    }
+
+    /// Joins the vertices of the 4 cells around every crossed edge starting
+    /// at one of the chunk's own voxels into a quad.
+    fn build_indices(&mut self) {
+        for z in 1..=SIZE {
+            for y in 1..=SIZE {
+                for x in 1..=SIZE {
+                    let pos    = [x, y, z];
+                    let inside = self.densities[Self::grid_idx(x, y, z)] > 0.0;
+                    for axis in 0..3 {
+                        let mut next = pos;
+                        next[axis] += 1;
+                        if inside == (self.densities[Self::grid_idx(next[0], next[1], next[2])] > 0.0) {
+                            continue;
+                        }
+
+                        // Cells around the edge, counter-clockwise around the axis
+                        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                        let quad = [(1, 1), (0, 1), (0, 0), (1, 0)].map(|(du, dv)| {
+                            let mut cell = pos;
+                            cell[u] -= du;
+                            cell[v] -= dv;
+                            self.cells[Self::grid_idx(cell[0], cell[1], cell[2])]
+                        });
+
+                        if inside {
+                            self.indices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                        } else {
+                            self.indices.extend_from_slice(&[quad[0], quad[2], quad[1], quad[0], quad[3], quad[2]]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    const fn grid_idx(x: usize, y: usize, z: usize) -> usize {
+        x + (y + z*Self::GRID_SIZE)*Self::GRID_SIZE
+    }
+
+    /// Replaces the normals of vertices with a flat gradient, such as the
+    /// saddles of a checkerboard, with the sum of their triangles' normals.
+    fn fill_flat_normals(&mut self) {
+        if !self.normals.iter().any(|&v| is_zero(v)) {
+            return;
+        }
+
+        let mut sums = vec![Vec3::ZERO; self.positions.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(self.positions[triangle[i] as usize]));
+            let normal = (b - a).cross(c - a);
+            for &i in triangle {
+                sums[i as usize] += normal;
+            }
+        }
+
+        for (normal, sum) in self.normals.iter_mut().zip(sums) {
+            if is_zero(*normal) {
+                *normal = sum.normalize_or(Vec3::Z).to_array();
+            }
+        }
+    }
+
+}
+
+/// Pairs of cell corners joined by an edge, corners indexed by `x | y << 1 | z << 2`.
+const CELL_EDGES: [(usize, usize); 12] = [
+    (0, 1), (2, 3), (4, 5), (6, 7),
+    (0, 2), (1, 3), (4, 6), (5, 7),
+    (0, 4), (1, 5), (2, 6), (3, 7),
+];
+
+const fn corner_offset(i: usize) -> Vec3 {
+    Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, (i >> 2) as f32)
+}
+
+fn is_zero(v: [f32; 3]) -> bool {
+    Vec3::from(v).cmpeq(Vec3::ZERO).all()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{tiles::{TileDefinition, TileRegistry}, world::{ChunkStorage, PosBlock}};
+
+    use super::*;
+
+    /// Every edge of a closed surface is shared by exactly two triangles,
+    /// running in opposite directions.
+    fn assert_closed<const SIZE: usize>(mesh: &SmoothMesh<SIZE>) {
+        let mut edges = HashMap::new();
+        for triangle in mesh.indices().chunks_exact(3) {
+            for i in 0..3 {
+                *edges.entry((triangle[i], triangle[(i + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "{a} {b}");
+            assert_eq!(edges.get(&(b, a)), Some(&1), "{a} {b}");
+        }
+    }
+
+    fn check_sphere<const SIZE: usize>() {
+        let centre = (SIZE / 2) as f32;
+        let radius = centre / 2.0;
+        let density = |pos: [isize; 3]| radius - Vec3::from_array(pos.map(|v| v as f32)).distance(Vec3::splat(centre));
+
+        let mut mesh = <SmoothMesh<SIZE>>::default();
+        mesh.build(&density);
+        assert!(!mesh.is_empty());
+        assert_eq!(mesh.positions().len(), mesh.normals().len());
+        assert_closed(&mesh);
+        for &position in mesh.positions() {
+            let distance = Vec3::from(position).distance(Vec3::splat(centre));
+            assert!((distance - radius).abs() < 1.0, "{position:?}");
+        }
+    }
+
+    #[test]
+    fn sphere_is_closed() {
+        check_sphere::<8>();
+        check_sphere::<32>();
+        check_sphere::<64>();
+    }
+
+    #[test]
+    fn single_voxel() {
+        let mut registry = TileRegistry::new();
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+        let mut chunk = <ChunkStorage<8>>::new_empty();
+        chunk.update(PosBlock::new(3, 4, 5), stone, &registry);
+
+        let mut mesh = <SmoothMesh<8>>::default();
+        mesh.build(&SolidDensity::new(&chunk));
+        assert_eq!(mesh.len(), 12);
+        assert_closed(&mesh);
+    }
+
+}
@@ -0,0 +1,134 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use core::fmt;
+use std::collections::HashMap;
+
+use super::{FaceVisibilityProvider, VertexError, VisFace};
+
+/// First problem found by [`validate_mesh`], with the index of the face.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshError {
+    /// The face word can't be decoded.
+    InvalidVertex{ index: usize, error: VertexError },
+    /// The face extends past the edge of the chunk.
+    OutOfBounds{ index: usize },
+    /// The face covers a voxel face already covered by an earlier face.
+    Duplicate{ index: usize, first: usize },
+    /// The face covers a voxel face that isn't visible.
+    NotVisible{ index: usize },
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidVertex{ index, error } => write!(f, "face {index} is invalid: {error}"),
+            Self::OutOfBounds{ index }          => write!(f, "face {index} extends past the chunk"),
+            Self::Duplicate{ index, first }     => write!(f, "face {index} overlaps face {first}"),
+            Self::NotVisible{ index }           => write!(f, "face {index} covers a hidden voxel face"),
+        }
+    }
+}
+
+impl core::error::Error for MeshError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::InvalidVertex{ error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Checks that every face of a chunk mesh decodes, lies within the chunk,
+/// covers only voxel faces visible in `vis` and doesn't overlap another face.
+/// Faces are decoded with the [`FaceVisibilityProvider::VERTEX_LAYOUT`] of `vis`.
+///
+/// Faces may be merged, but every visible voxel face isn't required to be
+/// covered. This is slow, meant for debug builds and meshes loaded from disk.
+pub fn validate_mesh<T: FaceVisibilityProvider>(faces: &[u32], vis: &T) -> Result<(), MeshError> {
+    let mut covered = HashMap::new();
+    for (index, &vert) in faces.iter().enumerate() {
+        let (x, y, layer, face) = T::VERTEX_LAYOUT.try_decode(vert).map_err(|error| MeshError::InvalidVertex{ index, error })?;
+        let (width, height) = T::VERTEX_LAYOUT.decode_size(vert);
+        if (x + width) as usize > T::CHUNK_SIZE || (y + height) as usize > T::CHUNK_SIZE {
+            return Err(MeshError::OutOfBounds{ index });
+        }
+
+        for u in x..x + width {
+            for v in y..y + height {
+                if let Some(&first) = covered.get(&(face, u, v, layer)) {
+                    return Err(MeshError::Duplicate{ index, first });
+                }
+                if !is_face_visible(vis, face, [u, v, layer]) {
+                    return Err(MeshError::NotVisible{ index });
+                }
+                covered.insert((face, u, v, layer), index);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether the unit face at the plane-local position is visible.
+fn is_face_visible(vis: &impl FaceVisibilityProvider, face: VisFace, local: [u8; 3]) -> bool {
+    let chunk = face.axis().to_world_usize(local.map(|v| (v / 8) as usize));
+    vis.get_face_visibility_plane(chunk, face, (local[2] % 8) as u32)
+        .get_at((local[0] % 8) as u32, (local[1] % 8) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{meshing::{ChunkMesh, ChunkMeshMode, VertexLayout}, tiles::{TileDefinition, TileRegistry}, world::{ChunkStorage, PosBlock}};
+
+    use super::*;
+
+    fn storage(blocks: impl IntoIterator<Item = PosBlock<16>>) -> ChunkStorage<16> {
+        let mut registry = TileRegistry::new();
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+        let mut storage = <ChunkStorage<16>>::new_empty();
+        for pos in blocks {
+            storage.update(pos, stone, &registry);
+        }
+        storage
+    }
+
+    #[test]
+    fn accepts_built_meshes() {
+        let storage = storage((0..16*16*16).map(PosBlock::from_idx).filter(|pos| pos.y < 3 + pos.x/5 || (pos.x + pos.z*3) % 5 == 0));
+        for mode in [ChunkMeshMode::Culled, ChunkMeshMode::Greedy] {
+            let mut mesh = ChunkMesh::new(mode);
+            mesh.build(&storage);
+            assert_eq!(validate_mesh(mesh.faces(), &storage), Ok(()), "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_faces() {
+        let storage = storage([PosBlock::new(4, 4, 4)]);
+        let layout  = VertexLayout::of::<16>();
+        let mut mesh = ChunkMesh::new(ChunkMeshMode::Greedy);
+        mesh.build(&storage);
+        let faces = mesh.faces();
+        assert_eq!(faces.len(), 6);
+
+        let invalid = faces[1] | (0x07 << (3*layout.coord_bits()));
+        assert_eq!(
+            validate_mesh(&[faces[0], invalid], &storage),
+            Err(MeshError::InvalidVertex{ index: 1, error: VertexError::InvalidFace(7) }),
+        );
+        assert_eq!(
+            validate_mesh(&[faces[0], faces[1], faces[0]], &storage),
+            Err(MeshError::Duplicate{ index: 2, first: 0 }),
+        );
+
+        let (x, y, layer, face) = layout.decode(faces[0]);
+        assert_eq!(
+            validate_mesh(&[layout.encode(x, y, layer + 1, face)], &storage),
+            Err(MeshError::NotVisible{ index: 0 }),
+        );
+        assert_eq!(
+            validate_mesh(&[layout.encode_sized(14, 0, 0, VisFace::PosY, 4, 1)], &storage),
+            Err(MeshError::OutOfBounds{ index: 0 }),
+        );
+    }
+
+}
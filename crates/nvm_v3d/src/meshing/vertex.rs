@@ -1,9 +1,186 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
+use core::fmt;
+
 use glam::IVec3;
 
+use crate::world::{ChunkDimensions, CHUNK_SIZE};
+
 use super::VisFace;
 
+/// Bit layout of encoded faces for a chunk size. Positions take the chunk's
+/// [`ChunkDimensions::COORD_BITS`] each, followed by the face, the size and
+/// the ambient occlusion:
+///
+/// ```text
+///  0..b     x
+///  b..2b    y
+/// 2b..3b    layer
+/// 3b..3b+3  face
+/// 3b+3..+3  width  - 1
+/// 3b+6..+3  height - 1
+/// 3b+9..+8  ambient occlusion, only for chunks up to 32 across
+/// ```
+///
+/// The free functions such as [`encode_vertex_sized`] use the layout of
+/// [`CHUNK_SIZE`] chunks, [`Self::DEFAULT`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    coord_bits: u32,
+}
+
+impl VertexLayout {
+
+    pub const DEFAULT: Self = Self::for_chunk_size(CHUNK_SIZE);
+
+    /// Layout for chunks `size` voxels across, panicking for sizes not
+    /// supported by [`ChunkDimensions`].
+    #[must_use]
+    pub const fn for_chunk_size(size: usize) -> Self {
+        assert!(size.is_power_of_two() && 8 <= size && size <= 64, "Chunk size must be a power of two from 8 to 64");
+        Self{ coord_bits: size.trailing_zeros() }
+    }
+
+    /// Layout for chunks `SIZE` voxels across.
+    #[must_use]
+    pub const fn of<const SIZE: usize>() -> Self {
+        Self{ coord_bits: ChunkDimensions::<SIZE>::COORD_BITS as u32 }
+    }
+
+    #[must_use]
+    pub const fn chunk_size(self) -> usize {
+        1 << self.coord_bits
+    }
+
+    /// Bits taken by each position, `b` in the layouts.
+    #[must_use]
+    pub const fn coord_bits(self) -> u32 {
+        self.coord_bits
+    }
+
+    /// Whether the ambient occlusion fits, see [`Self::with_ao`].
+    #[must_use]
+    pub const fn has_ao(self) -> bool {
+        self.ao_shift() + 8 <= u32::BITS
+    }
+
+    /// Encodes a unit face, see [`Self::encode_sized`].
+    #[must_use]
+    pub const fn encode(self, x: u8, y: u8, layer: u8, face: VisFace) -> u32 {
+        self.encode_sized(x, y, layer, face, 1, 1)
+    }
+
+    /// Encodes a face covering `width` by `height` voxels of a plane, starting
+    /// at `x`, `y`. Both sizes must be within `1..=8`.
+    #[must_use]
+    pub const fn encode_sized(
+        self,
+        x:      u8,
+        y:      u8,
+        layer:  u8,
+        face:   VisFace,
+        width:  u8,
+        height: u8,
+    ) -> u32 {
+        let bits = self.coord_bits;
+        let mask = self.coord_mask();
+        ((          x as u32) & mask)                 |
+        ((          y as u32) & mask) <<  bits        |
+        ((      layer as u32) & mask) << (2*bits)     |
+        (          face  as u32)      << (3*bits)     |
+        (((width -1) & 0x07) as u32)  << (3*bits + 3) |
+        (((height-1) & 0x07) as u32)  << (3*bits + 6)
+    }
+
+    #[must_use]
+    pub const fn decode(self, vert: u32) -> (
+        /*x:       */ u8,
+        /*y:       */ u8,
+        /*layer:   */ u8,
+        /*face:    */ VisFace,
+    ) {
+        match self.try_decode(vert) {
+            Ok(result) => result,
+            Err(_)     => panic!("Invalid vertex face"),
+        }
+    }
+
+    /// Decodes a vertex like [`Self::decode`], returning an error rather than
+    /// panicking on a corrupt face, eg. from a mesh read back from disk.
+    pub const fn try_decode(self, vert: u32) -> Result<(
+        /*x:       */ u8,
+        /*y:       */ u8,
+        /*layer:   */ u8,
+        /*face:    */ VisFace,
+    ), VertexError> {
+        let bits = self.coord_bits;
+        let mask = self.coord_mask();
+        let raw  = ((vert >> (3*bits)) & 0x07) as u8;
+        let Some(face) = VisFace::from_raw(raw as u16) else {
+            return Err(VertexError::InvalidFace(raw));
+        };
+        Ok((
+            ( vert              & mask) as u8,
+            ((vert >>    bits)  & mask) as u8,
+            ((vert >> (2*bits)) & mask) as u8,
+            face,
+        ))
+    }
+
+    #[must_use]
+    pub const fn decode_size(self, vert: u32) -> (
+        /*width:   */ u8,
+        /*height:  */ u8,
+    ) {
+        let bits = self.coord_bits;
+        (
+            (((vert >> (3*bits + 3)) & 0x07) + 1) as u8,
+            (((vert >> (3*bits + 6)) & 0x07) + 1) as u8,
+        )
+    }
+
+    /// Packs the ambient occlusion of each corner into the vertex, two bits
+    /// per corner with 3 being unoccluded. Corners are ordered by their
+    /// `(u, v)` position along the face basis: `(0, 0)`, `(1, 0)`, `(0, 1)`,
+    /// `(1, 1)`. Panics if the layout [`Self::has_ao`] is false.
+    #[must_use]
+    pub const fn with_ao(self, vert: u32, ao: u8) -> u32 {
+        assert!(self.has_ao(), "Ambient occlusion doesn't fit the vertex layout");
+        let shift = self.ao_shift();
+        (vert & ((1 << shift) - 1)) | ((ao as u32) << shift)
+    }
+
+    #[must_use]
+    pub const fn decode_ao(self, vert: u32) -> [u8; 4] {
+        if !self.has_ao() {
+            return [3; 4];
+        }
+        let shift = self.ao_shift();
+        [
+            ((vert >>  shift     ) & 0x03) as u8,
+            ((vert >> (shift + 2)) & 0x03) as u8,
+            ((vert >> (shift + 4)) & 0x03) as u8,
+            ((vert >> (shift + 6)) & 0x03) as u8,
+        ]
+    }
+
+    pub(crate) const fn coord_mask(self) -> u32 {
+        (1 << self.coord_bits) - 1
+    }
+
+    const fn ao_shift(self) -> u32 {
+        3*self.coord_bits + 9
+    }
+
+}
+
+impl Default for VertexLayout {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Encodes a unit face, see [`encode_vertex_sized`].
 #[must_use]
 pub const fn encode_vertex(
     x:     u8,
@@ -11,10 +188,32 @@ pub const fn encode_vertex(
     layer: u8,
     face:  VisFace,
 ) -> u32 {
-    ((    x & 0x1F) as u32)       |
-    ((    y & 0x1F) as u32) <<  5 |
-    ((layer & 0x1F) as u32) << 10 |
-    (         face  as u32) << 15
+    encode_vertex_sized(x, y, layer, face, 1, 1)
+}
+
+/// Encodes a face covering `width` by `height` voxels of a plane, starting
+/// at `x`, `y`. Both sizes must be within `1..=8`. Uses the
+/// [`VertexLayout::DEFAULT`] layout:
+///
+/// ```text
+///  0..5   x
+///  5..10  y
+/// 10..15  layer
+/// 15..18  face
+/// 18..21  width  - 1
+/// 21..24  height - 1
+/// 24..32  ambient occlusion, see [`with_vertex_ao`]
+/// ```
+#[must_use]
+pub const fn encode_vertex_sized(
+    x:      u8,
+    y:      u8,
+    layer:  u8,
+    face:   VisFace,
+    width:  u8,
+    height: u8,
+) -> u32 {
+    VertexLayout::DEFAULT.encode_sized(x, y, layer, face, width, height)
 }
 
 
@@ -25,16 +224,57 @@ pub const fn decode_vertex(vert: u32) -> (
     /*layer:   */ u8,
     /*face:    */ VisFace,
 ) {
-    (
-        ( vert        & 0x1F) as u8,
-        ((vert >>  5) & 0x1F) as u8,
-        ((vert >> 10) & 0x1F) as u8,
-        VisFace::from_raw(((vert >> 15) & 0x0F) as u16).unwrap(),
-    )
+    VertexLayout::DEFAULT.decode(vert)
 }
 
+/// Error decoding a face word that wasn't produced by [`encode_vertex_sized`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VertexError {
+    InvalidFace(u8),
+}
 
-fn get_face_basis(f: VisFace) -> [IVec3; 3] {
+impl fmt::Display for VertexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFace(face) => write!(f, "invalid vertex face {face}"),
+        }
+    }
+}
+
+impl core::error::Error for VertexError {}
+
+/// Decodes a vertex like [`decode_vertex`], returning an error rather than
+/// panicking on a corrupt face, eg. from a mesh read back from disk.
+pub const fn try_decode_vertex(vert: u32) -> Result<(
+    /*x:       */ u8,
+    /*y:       */ u8,
+    /*layer:   */ u8,
+    /*face:    */ VisFace,
+), VertexError> {
+    VertexLayout::DEFAULT.try_decode(vert)
+}
+
+#[must_use]
+pub const fn decode_vertex_size(vert: u32) -> (
+    /*width:   */ u8,
+    /*height:  */ u8,
+) {
+    VertexLayout::DEFAULT.decode_size(vert)
+}
+
+/// Packs the ambient occlusion of each corner into the vertex, see
+/// [`VertexLayout::with_ao`].
+#[must_use]
+pub const fn with_vertex_ao(vert: u32, ao: u8) -> u32 {
+    VertexLayout::DEFAULT.with_ao(vert, ao)
+}
+
+#[must_use]
+pub const fn decode_vertex_ao(vert: u32) -> [u8; 4] {
+    VertexLayout::DEFAULT.decode_ao(vert)
+}
+
+pub(crate) fn get_face_basis(f: VisFace) -> [IVec3; 3] {
     match f {
         VisFace::PosX => [-IVec3::Z,  IVec3::Y,  IVec3::X],
         VisFace::PosY => [ IVec3::X, -IVec3::Z,  IVec3::Y],
@@ -45,23 +285,15 @@ fn get_face_basis(f: VisFace) -> [IVec3; 3] {
     }
 }
 
+#[must_use]
 pub fn create_quad_for_vertex(x: u8, y: u8, layer: u8, face: VisFace) -> [IVec3; 6] {
+    create_quad_for_vertex_sized(x, y, layer, face, 1, 1)
+}
 
-    let basis = get_face_basis(face);
-    let basis_abs = basis.map(IVec3::abs);
-
-    let offset_corner   = IVec3::ONE - (basis[0] + basis[1] + basis[2]).max(IVec3::ZERO);
-    let offset_position = (x as i32)*basis_abs[0] + (y as i32)*basis_abs[1] + (layer as i32)*basis_abs[2];
-    let offset_base     = offset_corner + offset_position;
-
-    let p: [IVec3; 4] = [
-        offset_base,
-        offset_base + basis[0],
-        offset_base + basis[1],
-        offset_base + basis[0] + basis[1],
-    ];
-
-    if basis[2].dot(IVec3::ONE) < 0 {
+#[must_use]
+pub fn create_quad_for_vertex_sized(x: u8, y: u8, layer: u8, face: VisFace, width: u8, height: u8) -> [IVec3; 6] {
+    let p = create_quad_corners_sized(x, y, layer, face, width, height);
+    if get_face_basis(face)[2].dot(IVec3::ONE) < 0 {
         [
             p[0], p[1], p[2],
             p[3], p[2], p[1],
@@ -72,5 +304,58 @@ pub fn create_quad_for_vertex(x: u8, y: u8, layer: u8, face: VisFace) -> [IVec3;
             p[3], p[1], p[2],
         ]
     }
+}
 
-}
\ No newline at end of file
+/// Corners of a face ordered by their `(u, v)` position along the face
+/// basis, like the corners of [`with_vertex_ao`].
+pub(crate) fn create_quad_corners_sized(x: u8, y: u8, layer: u8, face: VisFace, width: u8, height: u8) -> [IVec3; 4] {
+
+    let basis = get_face_basis(face);
+    let basis_abs = basis.map(IVec3::abs);
+
+    // Axes running in the negative direction start from the far side of the quad
+    let offset_size     = (-basis[0]).max(IVec3::ZERO)*(width as i32 - 1) + (-basis[1]).max(IVec3::ZERO)*(height as i32 - 1);
+    let offset_corner   = IVec3::ONE - (basis[0] + basis[1] + basis[2]).max(IVec3::ZERO);
+    let offset_position = (x as i32)*basis_abs[0] + (y as i32)*basis_abs[1] + (layer as i32)*basis_abs[2];
+    let offset_base     = offset_corner + offset_position + offset_size;
+
+    let u = basis[0]*(width  as i32);
+    let v = basis[1]*(height as i32);
+
+    [
+        offset_base,
+        offset_base + u,
+        offset_base + v,
+        offset_base + u + v,
+    ]
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ao_round_trip() {
+        for size in [16, 32] {
+            let layout = VertexLayout::for_chunk_size(size);
+            let max    = (size - 1) as u8;
+            assert!(layout.has_ao());
+            for face in VisFace::ALL {
+                let vert = layout.encode_sized(max, 0, max, face, 8, 3);
+                for ao in 0..=u8::MAX {
+                    let packed = layout.with_ao(vert, ao);
+                    assert_eq!(layout.decode(packed), (max, 0, max, face));
+                    assert_eq!(layout.decode_size(packed), (8, 3));
+                    assert_eq!(layout.decode_ao(packed), [ao & 0x03, (ao >> 2) & 0x03, (ao >> 4) & 0x03, ao >> 6]);
+                    assert_eq!(layout.with_ao(packed, 0xFF), layout.with_ao(vert, 0xFF));
+                }
+            }
+        }
+
+        let layout = VertexLayout::for_chunk_size(64);
+        assert!(!layout.has_ao());
+        assert_eq!(layout.decode_ao(layout.encode(63, 63, 63, VisFace::NegZ)), [3; 4]);
+    }
+
+}
@@ -0,0 +1,313 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+//! Binary chunk format.
+//!
+//! All values are little-endian.
+//!
+//! ```text
+//! Header
+//!   magic        [u8; 4]    "NVMC"
+//!   version      u16        CHUNK_FORMAT_VERSION
+//!   flags        u8         bit 0: light channels present
+//!   reserved     u8         0
+//!   position     [i16; 3]   PosChunk x, y, z
+//!
+//! Identifiers
+//!   palette_len  u16        number of palette entries, at least 1
+//!   palette      [state; palette_len]
+//!     length     u16
+//!     state      [u8; length]   UTF-8 tile state
+//!   bits         u8         0 if palette_len is 1, otherwise 1, 2, 4, 8 or 16
+//!   words        [u64; CHUNK_LENGTH*bits/64]
+//!
+//! Light (only if flag bit 0 is set)
+//!   channels     [[u8; 4]; CHUNK_LENGTH]
+//! ```
+//!
+//! Palette indices are packed from the least significant bit of each word,
+//! in `PosBlock::to_idx` order. Visibility planes are not stored, they're
+//! rebuilt from the identifiers on load.
+//!
+//! The palette doubles as the chunk's name table. Runtime identifiers depend
+//! on registration order, so tiles are stored by name and remapped to the
+//! current registry on load. States are written as
+//! `TileRegistry::get_state_string`, eg. `core:stairs[facing=pos_x,half=top]`.
+//! Names that are no longer registered load as `TileIdentifier::DEFAULT`,
+//! unknown properties load at their default value.
+
+use core::fmt;
+use std::io::{self, Read, Write};
+
+use crate::{lighting::LightStorageChunk, tiles::{TileIdentifier, TileRegistry}, world::{ChunkIdentifierMode, ChunkStorage, IdentifierPalette, PosChunk, CHUNK_LENGTH}};
+
+pub const CHUNK_FORMAT_MAGIC:   [u8; 4] = *b"NVMC";
+pub const CHUNK_FORMAT_VERSION: u16     = 1;
+
+const FLAG_LIGHT: u8 = 1 << 0;
+
+#[derive(Debug)]
+pub enum ChunkFormatError {
+    Io(io::Error),
+    InvalidMagic([u8; 4]),
+    UnsupportedVersion(u16),
+    InvalidPalette,
+    InvalidBitWidth(u8),
+    InvalidTileName,
+}
+
+impl fmt::Display for ChunkFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e)                  => write!(f, "io error: {e}"),
+            Self::InvalidMagic(magic)    => write!(f, "invalid chunk magic {magic:?}"),
+            Self::UnsupportedVersion(v)  => write!(f, "unsupported chunk format version {v}"),
+            Self::InvalidPalette         => write!(f, "chunk palette is inconsistent with its data"),
+            Self::InvalidBitWidth(bits)  => write!(f, "invalid palette bit width {bits}"),
+            Self::InvalidTileName        => write!(f, "invalid tile state in palette"),
+        }
+    }
+}
+
+impl core::error::Error for ChunkFormatError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ChunkFormatError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// A chunk and its optional light data, as stored on disk.
+#[derive(Debug)]
+pub struct ChunkRecord {
+    pub pos:   PosChunk,
+    pub tiles: ChunkStorage,
+    pub light: Option<LightStorageChunk>,
+}
+
+impl ChunkRecord {
+
+    pub fn write_to(&self, writer: impl Write, registry: &TileRegistry) -> Result<(), ChunkFormatError> {
+        write_chunk_to(writer, self.pos, &self.tiles, self.light.as_ref(), registry)
+    }
+
+    /// Reads a chunk, remapping its tiles by name and rebuilding its
+    /// visibility planes from the registry.
+    pub fn read_from(
+        mut reader: impl Read,
+        mode:       ChunkIdentifierMode,
+        registry:   &TileRegistry,
+    ) -> Result<Self, ChunkFormatError> {
+        let magic: [u8; 4] = read_array(&mut reader)?;
+        if magic != CHUNK_FORMAT_MAGIC {
+            return Err(ChunkFormatError::InvalidMagic(magic));
+        }
+
+        let version = u16::from_le_bytes(read_array(&mut reader)?);
+        if version != CHUNK_FORMAT_VERSION {
+            return Err(ChunkFormatError::UnsupportedVersion(version));
+        }
+
+        let [flags, _] = read_array(&mut reader)?;
+        let pos = PosChunk::new(
+            i16::from_le_bytes(read_array(&mut reader)?),
+            i16::from_le_bytes(read_array(&mut reader)?),
+            i16::from_le_bytes(read_array(&mut reader)?),
+        );
+
+        let palette_len = u16::from_le_bytes(read_array(&mut reader)?) as usize;
+        if palette_len == 0 || palette_len > CHUNK_LENGTH {
+            return Err(ChunkFormatError::InvalidPalette);
+        }
+        let mut palette = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            let id = registry.get_by_state_string(&read_tile_state(&mut reader)?);
+            palette.push(id.unwrap_or(TileIdentifier::DEFAULT));
+        }
+
+        let [bits] = read_array(&mut reader)?;
+        let palette = match bits {
+            0 if palette_len == 1 => IdentifierPalette::new_filled(palette[0]),
+            1 | 2 | 4 | 8 | 16 => {
+                let mut words = vec![0; CHUNK_LENGTH * (bits as usize) / 64].into_boxed_slice();
+                for word in &mut words {
+                    *word = u64::from_le_bytes(read_array(&mut reader)?);
+                }
+                IdentifierPalette::from_raw_parts(palette, bits as u32, words).ok_or(ChunkFormatError::InvalidPalette)?
+            },
+            _ => return Err(ChunkFormatError::InvalidBitWidth(bits)),
+        };
+
+        let light = if flags & FLAG_LIGHT != 0 {
+            let mut data: Box<[[u8; 4]; CHUNK_LENGTH]> = vec![[0; 4]; CHUNK_LENGTH].into_boxed_slice().try_into().unwrap();
+            reader.read_exact(data.as_flattened_mut())?;
+            Some(LightStorageChunk::from_data(data))
+        } else {
+            None
+        };
+
+        Ok(Self{
+            pos,
+            tiles: ChunkStorage::from_identifier_palette(palette, mode, registry),
+            light,
+        })
+    }
+
+}
+
+/// Writes a chunk without requiring ownership of its storage, see
+/// [`ChunkRecord::write_to`].
+pub fn write_chunk_to(
+    mut writer: impl Write,
+    pos:        PosChunk,
+    tiles:      &ChunkStorage,
+    light:      Option<&LightStorageChunk>,
+    registry:   &TileRegistry,
+) -> Result<(), ChunkFormatError> {
+    let flags = if light.is_some() { FLAG_LIGHT } else { 0 };
+
+    writer.write_all(&CHUNK_FORMAT_MAGIC)?;
+    writer.write_all(&CHUNK_FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&[flags, 0])?;
+    for v in [pos.x, pos.y, pos.z] {
+        writer.write_all(&v.to_le_bytes())?;
+    }
+
+    if let Some(id) = tiles.get_uniform() {
+        writer.write_all(&1_u16.to_le_bytes())?;
+        write_tile_state(&mut writer, &registry.get_state_string(id))?;
+        writer.write_all(&[0])?;
+    } else {
+        let palette = tiles.to_identifier_palette();
+        writer.write_all(&(palette.palette().len() as u16).to_le_bytes())?;
+        for &id in palette.palette() {
+            write_tile_state(&mut writer, &registry.get_state_string(id))?;
+        }
+        writer.write_all(&[palette.bits_per_voxel() as u8])?;
+        for word in palette.words() {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+    }
+
+    if let Some(light) = light {
+        writer.write_all(light.get_data().as_flattened())?;
+    }
+
+    Ok(())
+}
+
+fn write_tile_state(writer: &mut impl Write, state: &str) -> io::Result<()> {
+    writer.write_all(&(state.len() as u16).to_le_bytes())?;
+    writer.write_all(state.as_bytes())
+}
+
+fn read_tile_state(reader: &mut impl Read) -> Result<String, ChunkFormatError> {
+    let len = u16::from_le_bytes(read_array(reader)?);
+    let mut state = vec![0; len as usize];
+    reader.read_exact(&mut state)?;
+    String::from_utf8(state).map_err(|_| ChunkFormatError::InvalidTileName)
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut result = [0; N];
+    reader.read_exact(&mut result)?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tiles::{TileDefinition, TileProperty}, world::PosBlock};
+
+    use super::*;
+
+    fn registry(names: &[&str]) -> TileRegistry {
+        let mut registry = TileRegistry::new();
+        for &name in names {
+            let definition = TileDefinition::new_opaque(name).with_property(TileProperty::new("level", &["low", "mid", "high"]));
+            registry.register(name, definition).unwrap();
+        }
+        registry
+    }
+
+    fn write(pos: PosChunk, tiles: &ChunkStorage, light: Option<&LightStorageChunk>, registry: &TileRegistry) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_chunk_to(&mut data, pos, tiles, light, registry).unwrap();
+        data
+    }
+
+    #[test]
+    fn round_trip_remaps_by_name() {
+        let saved  = registry(&["test:a", "test:b", "test:c"]);
+        let loaded = registry(&["test:c", "test:a"]);
+        let [a, b, c] = ["test:a", "test:b", "test:c"].map(|name| saved.get_by_name(name).unwrap());
+        let a_high = saved.with_property(a, "level", 2).unwrap();
+
+        let mut tiles = ChunkStorage::new_empty();
+        for idx in 0..CHUNK_LENGTH {
+            let id = [TileIdentifier::DEFAULT, a, b, c, a_high][idx*7 % 5];
+            tiles.update(PosBlock::from_idx(idx), id, &saved);
+        }
+        let mut light = LightStorageChunk::default();
+        light.raise_channel(123, 2, 9);
+        let data = write(PosChunk::new(-3, 4, 5), &tiles, Some(&light), &saved);
+
+        for mode in [ChunkIdentifierMode::Direct, ChunkIdentifierMode::Palette] {
+            let record = ChunkRecord::read_from(data.as_slice(), mode, &loaded).unwrap();
+            assert_eq!(record.pos, PosChunk::new(-3, 4, 5));
+            assert_eq!(record.light.unwrap().get_channel(123, 2), 9);
+            for idx in 0..CHUNK_LENGTH {
+                let pos = PosBlock::from_idx(idx);
+                let expected = loaded.get_by_state_string(&saved.get_state_string(tiles.get(pos))).unwrap_or(TileIdentifier::DEFAULT);
+                assert_eq!(record.tiles.get(pos), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_uniform() {
+        let registry = registry(&["test:a"]);
+        let a = registry.get_by_name("test:a").unwrap();
+        let tiles = ChunkStorage::new_uniform(a, &registry);
+        let record = ChunkRecord::read_from(write(PosChunk::new(0, 0, 0), &tiles, None, &registry).as_slice(), ChunkIdentifierMode::Palette, &registry).unwrap();
+        assert_eq!(record.tiles.get_uniform(), Some(a));
+        assert!(record.light.is_none());
+    }
+
+    #[test]
+    fn truncated_input() {
+        let registry = registry(&["test:a"]);
+        let mut tiles = ChunkStorage::new_empty();
+        tiles.update(PosBlock::new(1, 2, 3), registry.get_by_name("test:a").unwrap(), &registry);
+        let data = write(PosChunk::new(0, 0, 0), &tiles, Some(&LightStorageChunk::default()), &registry);
+        for len in (0..data.len()).step_by(97).chain([data.len() - 1]) {
+            let result = ChunkRecord::read_from(&data[..len], ChunkIdentifierMode::Palette, &registry);
+            assert!(matches!(result, Err(ChunkFormatError::Io(_))), "{len}");
+        }
+    }
+
+    #[test]
+    fn invalid_header() {
+        let registry = registry(&[]);
+        let data = write(PosChunk::new(0, 0, 0), &ChunkStorage::new_empty(), None, &registry);
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(ChunkRecord::read_from(bad_magic.as_slice(), ChunkIdentifierMode::Palette, &registry), Err(ChunkFormatError::InvalidMagic(_))));
+
+        let mut bad_version = data.clone();
+        bad_version[4..6].copy_from_slice(&(CHUNK_FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(ChunkRecord::read_from(bad_version.as_slice(), ChunkIdentifierMode::Palette, &registry), Err(ChunkFormatError::UnsupportedVersion(_))));
+
+        let mut bad_bits = data;
+        let last = bad_bits.len() - 1;
+        bad_bits[last] = 3;
+        assert!(matches!(ChunkRecord::read_from(bad_bits.as_slice(), ChunkIdentifierMode::Palette, &registry), Err(ChunkFormatError::InvalidBitWidth(3))));
+    }
+
+}
@@ -0,0 +1,10 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+mod chunk_format;
+pub use chunk_format::*;
+
+mod region_file;
+pub use region_file::*;
+
+mod region_store;
+pub use region_store::*;
@@ -0,0 +1,463 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+//! Region file format, packing `REGION_SIZE`³ chunks into a single file.
+//!
+//! The file is divided into `REGION_SECTOR_SIZE` byte sectors. All values
+//! are little-endian.
+//!
+//! ```text
+//! Header (REGION_HEADER_SECTORS sectors)
+//!   magic        [u8; 4]    "NVMR"
+//!   version      u16        REGION_FORMAT_VERSION
+//!   coord_bits   u8         REGION_COORD_BITS
+//!   reserved     u8         0
+//!   entries      [(sector: u32, count: u32); REGION_LENGTH]
+//!
+//! Chunk blob (starting at `sector`, spanning `count` sectors)
+//!   length       u32        length of the payload in bytes
+//!   compression  u8         see RegionCompression
+//!   payload      [u8; length]
+//! ```
+//!
+//! An entry with a sector count of zero is absent. Entries are indexed with
+//! [`PosRegion::from_chunk`]. Blobs are rewritten in place when they still
+//! fit in their sectors, otherwise they're moved to the first free run of
+//! sectors large enough to hold them.
+
+use core::fmt;
+use std::{borrow::Cow, fs::{File, OpenOptions}, io::{self, Read, Seek, SeekFrom, Write}, path::Path};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::world::PosChunk;
+
+use super::ChunkFormatError;
+
+pub const REGION_COORD_BITS:  usize = 3;
+pub const REGION_SIZE:        usize = 1 << REGION_COORD_BITS;
+pub const REGION_LENGTH:      usize = REGION_SIZE*REGION_SIZE*REGION_SIZE;
+pub const REGION_SECTOR_SIZE: usize = 4096;
+
+pub const REGION_FORMAT_MAGIC:   [u8; 4] = *b"NVMR";
+pub const REGION_FORMAT_VERSION: u16     = 1;
+
+pub const REGION_HEADER_SIZE:    usize = 8 + REGION_LENGTH*8;
+pub const REGION_HEADER_SECTORS: usize = REGION_HEADER_SIZE.div_ceil(REGION_SECTOR_SIZE);
+
+const BLOB_HEADER_SIZE: usize = 5;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PosRegion {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+impl PosRegion {
+
+    #[must_use]
+    pub const fn new(x: i16, y: i16, z: i16) -> Self {
+        Self{x, y, z}
+    }
+
+    /// Returns the region containing the chunk and the chunk's entry index
+    /// within that region.
+    #[must_use]
+    pub const fn from_chunk(pos: PosChunk) -> (Self, usize) {
+        const MASK: i16 = (REGION_SIZE - 1) as i16;
+        (
+            Self::new(
+                pos.x >> REGION_COORD_BITS,
+                pos.y >> REGION_COORD_BITS,
+                pos.z >> REGION_COORD_BITS,
+            ),
+            ((pos.x & MASK) as usize) | (((pos.y & MASK) as usize) << REGION_COORD_BITS) | (((pos.z & MASK) as usize) << (2*REGION_COORD_BITS)),
+        )
+    }
+
+    #[must_use]
+    pub const fn to_chunk(self, idx: usize) -> PosChunk {
+        const MASK: usize = REGION_SIZE - 1;
+        PosChunk::new(
+            (self.x << REGION_COORD_BITS) | ( idx                           & MASK) as i16,
+            (self.y << REGION_COORD_BITS) | ((idx >>    REGION_COORD_BITS ) & MASK) as i16,
+            (self.z << REGION_COORD_BITS) | ((idx >> (2*REGION_COORD_BITS)) & MASK) as i16,
+        )
+    }
+
+}
+
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegionCompression {
+    None = 0,
+    #[default]
+    Zlib = 1,
+}
+
+impl RegionCompression {
+
+    #[must_use]
+    pub const fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            0 => Some(Self::None),
+            1 => Some(Self::Zlib),
+            _ => None,
+        }
+    }
+
+}
+
+#[derive(Debug)]
+pub enum RegionError {
+    Io(io::Error),
+    Chunk(ChunkFormatError),
+    InvalidHeader,
+    CorruptEntry(usize),
+    UnknownCompression(u8),
+    PositionMismatch{ expected: PosChunk, found: PosChunk },
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e)                  => write!(f, "io error: {e}"),
+            Self::Chunk(e)               => write!(f, "chunk error: {e}"),
+            Self::InvalidHeader          => write!(f, "invalid region header"),
+            Self::CorruptEntry(idx)      => write!(f, "region entry {idx} is corrupt"),
+            Self::UnknownCompression(c)  => write!(f, "unknown region compression {c}"),
+            Self::PositionMismatch{ expected, found } => write!(f, "expected chunk {expected:?}, found {found:?}"),
+        }
+    }
+}
+
+impl core::error::Error for RegionError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::Io(e)    => Some(e),
+            Self::Chunk(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RegionError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ChunkFormatError> for RegionError {
+    fn from(value: ChunkFormatError) -> Self {
+        match value {
+            ChunkFormatError::Io(e) => Self::Io(e),
+            e => Self::Chunk(e),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct RegionEntry {
+    sector: u32,
+    count:  u32,
+}
+
+impl RegionEntry {
+    const fn sectors(self) -> core::ops::Range<usize> {
+        (self.sector as usize)..(self.sector as usize + self.count as usize)
+    }
+
+    /// The sectors of the entry, if they lie past the header and within the
+    /// first `len` sectors.
+    fn checked_sectors(self, len: usize) -> Option<core::ops::Range<usize>> {
+        let start = self.sector as usize;
+        let end   = start.checked_add(self.count as usize)?;
+        (start >= REGION_HEADER_SECTORS && end <= len).then_some(start..end)
+    }
+}
+
+#[derive(Debug)]
+pub struct RegionFile<S = File> {
+    storage: S,
+    entries: Box<[RegionEntry; REGION_LENGTH]>,
+    used:    Vec<bool>,
+}
+
+impl RegionFile<File> {
+
+    /// Opens the region file at `path`, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RegionError> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        Self::new(file)
+    }
+
+}
+
+impl<S: Read + Write + Seek> RegionFile<S> {
+
+    /// Reads the region header from `storage`, or writes a fresh one if the
+    /// storage is empty.
+    pub fn new(mut storage: S) -> Result<Self, RegionError> {
+        let len = storage.seek(SeekFrom::End(0))? as usize;
+        if len == 0 {
+            let mut header = vec![0; REGION_HEADER_SECTORS*REGION_SECTOR_SIZE];
+            header[0..4].copy_from_slice(&REGION_FORMAT_MAGIC);
+            header[4..6].copy_from_slice(&REGION_FORMAT_VERSION.to_le_bytes());
+            header[6] = REGION_COORD_BITS as u8;
+            storage.seek(SeekFrom::Start(0))?;
+            storage.write_all(&header)?;
+            return Ok(Self{
+                storage,
+                entries: vec![RegionEntry::default(); REGION_LENGTH].into_boxed_slice().try_into().unwrap(),
+                used:    vec![true; REGION_HEADER_SECTORS],
+            });
+        }
+
+        if len < REGION_HEADER_SIZE {
+            return Err(RegionError::InvalidHeader);
+        }
+
+        let mut header = vec![0; REGION_HEADER_SIZE];
+        storage.seek(SeekFrom::Start(0))?;
+        storage.read_exact(&mut header)?;
+        if header[0..4] != REGION_FORMAT_MAGIC
+            || u16::from_le_bytes([header[4], header[5]]) != REGION_FORMAT_VERSION
+            || header[6] as usize != REGION_COORD_BITS
+        {
+            return Err(RegionError::InvalidHeader);
+        }
+
+        let mut used = vec![false; len.div_ceil(REGION_SECTOR_SIZE).max(REGION_HEADER_SECTORS)];
+        used[..REGION_HEADER_SECTORS].fill(true);
+
+        let mut entries: Box<[RegionEntry; REGION_LENGTH]> = vec![RegionEntry::default(); REGION_LENGTH].into_boxed_slice().try_into().unwrap();
+        for (idx, (entry, raw)) in entries.iter_mut().zip(header[8..].chunks_exact(8)).enumerate() {
+            *entry = RegionEntry{
+                sector: u32::from_le_bytes(raw[0..4].try_into().unwrap()),
+                count:  u32::from_le_bytes(raw[4..8].try_into().unwrap()),
+            };
+            if entry.count == 0 {
+                continue;
+            }
+
+            let Some(sectors) = entry.checked_sectors(used.len()) else {
+                return Err(RegionError::CorruptEntry(idx));
+            };
+            if used[sectors.clone()].iter().any(|&v| v) {
+                return Err(RegionError::CorruptEntry(idx));
+            }
+            used[sectors].fill(true);
+        }
+
+        Ok(Self{ storage, entries, used })
+    }
+
+    #[must_use]
+    pub fn contains(&self, idx: usize) -> bool {
+        self.entries[idx].count != 0
+    }
+
+    /// Reads and decompresses the blob stored at `idx`.
+    pub fn read(&mut self, idx: usize) -> Result<Option<Vec<u8>>, RegionError> {
+        let entry = self.entries[idx];
+        if entry.count == 0 {
+            return Ok(None);
+        }
+
+        let mut header = [0; BLOB_HEADER_SIZE];
+        self.storage.seek(SeekFrom::Start((entry.sector as u64)*(REGION_SECTOR_SIZE as u64)))?;
+        self.storage.read_exact(&mut header)?;
+
+        let length = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        if BLOB_HEADER_SIZE + length > (entry.count as usize)*REGION_SECTOR_SIZE {
+            return Err(RegionError::CorruptEntry(idx));
+        }
+
+        let mut payload = vec![0; length];
+        self.storage.read_exact(&mut payload)?;
+
+        match RegionCompression::from_raw(header[4]) {
+            Some(RegionCompression::None) => Ok(Some(payload)),
+            Some(RegionCompression::Zlib) => {
+                let mut result = Vec::new();
+                ZlibDecoder::new(payload.as_slice()).read_to_end(&mut result)?;
+                Ok(Some(result))
+            },
+            None => Err(RegionError::UnknownCompression(header[4])),
+        }
+    }
+
+    /// Compresses and writes `data` to `idx`, rewriting the existing blob in
+    /// place if the result still fits in its sectors.
+    pub fn write(&mut self, idx: usize, data: &[u8], compression: RegionCompression) -> Result<(), RegionError> {
+        let payload = match compression {
+            RegionCompression::None => Cow::Borrowed(data),
+            RegionCompression::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                Cow::Owned(encoder.finish()?)
+            },
+        };
+
+        let count = (BLOB_HEADER_SIZE + payload.len()).div_ceil(REGION_SECTOR_SIZE);
+        let entry = self.entries[idx];
+        let sector = if (entry.count as usize) >= count {
+            self.used[(entry.sector as usize + count)..entry.sectors().end].fill(false);
+            entry.sector as usize
+        } else {
+            self.used[entry.sectors()].fill(false);
+            self.allocate(count)
+        };
+
+        let mut blob = Vec::with_capacity(count*REGION_SECTOR_SIZE);
+        blob.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        blob.push(compression as u8);
+        blob.extend_from_slice(&payload);
+        blob.resize(count*REGION_SECTOR_SIZE, 0);
+
+        self.storage.seek(SeekFrom::Start((sector as u64)*(REGION_SECTOR_SIZE as u64)))?;
+        self.storage.write_all(&blob)?;
+
+        self.write_entry(idx, RegionEntry{ sector: sector as u32, count: count as u32 })
+    }
+
+    pub fn remove(&mut self, idx: usize) -> Result<(), RegionError> {
+        let entry = self.entries[idx];
+        if entry.count == 0 {
+            return Ok(());
+        }
+        self.used[entry.sectors()].fill(false);
+        self.write_entry(idx, RegionEntry::default())
+    }
+
+    pub fn flush(&mut self) -> Result<(), RegionError> {
+        Ok(self.storage.flush()?)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+
+}
+
+impl<S: Read + Write + Seek> RegionFile<S> {
+
+    fn write_entry(&mut self, idx: usize, entry: RegionEntry) -> Result<(), RegionError> {
+        let mut raw = [0; 8];
+        raw[0..4].copy_from_slice(&entry.sector.to_le_bytes());
+        raw[4..8].copy_from_slice(&entry.count.to_le_bytes());
+        self.storage.seek(SeekFrom::Start((8 + idx*8) as u64))?;
+        self.storage.write_all(&raw)?;
+        self.entries[idx] = entry;
+        Ok(())
+    }
+
+    /// First-fit allocation of `count` sectors, growing the file if no free
+    /// run is large enough.
+    fn allocate(&mut self, count: usize) -> usize {
+        let mut run_start = REGION_HEADER_SECTORS;
+        let mut run_len   = 0;
+        for i in REGION_HEADER_SECTORS..self.used.len() {
+            if self.used[i] {
+                run_start = i + 1;
+                run_len   = 0;
+                continue;
+            }
+            run_len += 1;
+            if run_len == count {
+                break;
+            }
+        }
+
+        if run_start + count > self.used.len() {
+            self.used.resize(run_start + count, false);
+        }
+        self.used[run_start..(run_start + count)].fill(true);
+        run_start
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use io::Cursor;
+
+    fn region_with(entries: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut region = RegionFile::new(Cursor::new(Vec::new())).unwrap();
+        for &(idx, data) in entries {
+            region.write(idx, data, RegionCompression::Zlib).unwrap();
+        }
+        region.into_inner().into_inner()
+    }
+
+    fn set_entry(bytes: &mut [u8], idx: usize, sector: u32, count: u32) {
+        bytes[8 + idx*8..12 + idx*8].copy_from_slice(&sector.to_le_bytes());
+        bytes[12 + idx*8..16 + idx*8].copy_from_slice(&count.to_le_bytes());
+    }
+
+    #[test]
+    fn round_trip() {
+        let large = vec![7; 3*REGION_SECTOR_SIZE];
+        let bytes = region_with(&[(0, b"first"), (5, &large), (REGION_LENGTH - 1, b"last")]);
+
+        let mut region = RegionFile::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(region.read(0).unwrap().as_deref(), Some(b"first".as_slice()));
+        assert_eq!(region.read(5).unwrap(), Some(large));
+        assert_eq!(region.read(REGION_LENGTH - 1).unwrap().as_deref(), Some(b"last".as_slice()));
+        assert_eq!(region.read(1).unwrap(), None);
+    }
+
+    #[test]
+    fn rewrite_and_remove() {
+        let mut region = RegionFile::new(Cursor::new(region_with(&[(1, b"a"), (2, b"b")]))).unwrap();
+        let large: Vec<u8> = (0..2*REGION_SECTOR_SIZE).map(|v| (v*31) as u8).collect();
+        region.write(1, &large, RegionCompression::None).unwrap();
+        region.remove(2).unwrap();
+        region.write(3, b"c", RegionCompression::None).unwrap();
+
+        let mut region = RegionFile::new(Cursor::new(region.into_inner().into_inner())).unwrap();
+        assert_eq!(region.read(1).unwrap(), Some(large));
+        assert!(!region.contains(2));
+        assert_eq!(region.read(3).unwrap().as_deref(), Some(b"c".as_slice()));
+    }
+
+    #[test]
+    fn invalid_header() {
+        let bytes = region_with(&[]);
+        assert!(matches!(RegionFile::new(Cursor::new(bytes[..REGION_HEADER_SIZE - 1].to_vec())), Err(RegionError::InvalidHeader)));
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(RegionFile::new(Cursor::new(bad_magic)), Err(RegionError::InvalidHeader)));
+
+        let mut bad_version = bytes;
+        bad_version[4] = 0xFF;
+        assert!(matches!(RegionFile::new(Cursor::new(bad_version)), Err(RegionError::InvalidHeader)));
+    }
+
+    #[test]
+    fn corrupt_entry() {
+        let bytes = region_with(&[(4, b"data")]);
+        for (sector, count) in [(u32::MAX, 1), (1, u32::MAX), (0, 1), (REGION_HEADER_SECTORS as u32 + 100, 1)] {
+            let mut corrupt = bytes.clone();
+            set_entry(&mut corrupt, 5, sector, count);
+            assert!(matches!(RegionFile::new(Cursor::new(corrupt)), Err(RegionError::CorruptEntry(5))), "{sector} {count}");
+        }
+
+        let mut overlapping = bytes;
+        set_entry(&mut overlapping, 5, REGION_HEADER_SECTORS as u32, 1);
+        assert!(matches!(RegionFile::new(Cursor::new(overlapping)), Err(RegionError::CorruptEntry(5))));
+    }
+
+    #[test]
+    fn truncated_blob() {
+        let mut bytes = region_with(&[(0, b"data")]);
+        let sector = REGION_HEADER_SECTORS*REGION_SECTOR_SIZE;
+        bytes[sector..sector + 4].copy_from_slice(&(REGION_SECTOR_SIZE as u32).to_le_bytes());
+        let mut region = RegionFile::new(Cursor::new(bytes)).unwrap();
+        assert!(matches!(region.read(0), Err(RegionError::CorruptEntry(0))));
+    }
+
+}
@@ -0,0 +1,271 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use std::{collections::{hash_map::Entry, HashMap}, fs, path::PathBuf};
+
+use crate::{lighting::{LightStorageChunk, LightStorageWorld}, tiles::TileRegistry, world::{ChunkIdentifierMode, ChunkStorage, PosChunk, World}};
+
+use super::{write_chunk_to, ChunkRecord, PosRegion, RegionCompression, RegionError, RegionFile};
+
+/// Default number of region files a [`RegionStore`] keeps open at once.
+pub const REGION_STORE_MAX_OPEN: usize = 64;
+
+/// Directory of region files, opened lazily as chunks are loaded and saved.
+/// Once more than [`RegionStore::max_open`] regions are open, the least
+/// recently used region is flushed and closed.
+#[derive(Debug)]
+pub struct RegionStore {
+    root:        PathBuf,
+    compression: RegionCompression,
+    mode:        ChunkIdentifierMode,
+    max_open:    usize,
+    tick:        u64,
+    regions:     HashMap<PosRegion, OpenRegion>,
+}
+
+#[derive(Debug)]
+struct OpenRegion {
+    file:      RegionFile,
+    last_used: u64,
+}
+
+impl RegionStore {
+
+    #[must_use]
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self{
+            root:        root.into(),
+            compression: RegionCompression::default(),
+            mode:        ChunkIdentifierMode::Palette,
+            max_open:    REGION_STORE_MAX_OPEN,
+            tick:        0,
+            regions:     HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub const fn max_open(&self) -> usize {
+        self.max_open
+    }
+
+    /// Sets how many region files may be open at once, at least one. Regions
+    /// over the limit are closed the next time a region is opened.
+    pub fn set_max_open(&mut self, max_open: usize) {
+        self.max_open = max_open.max(1);
+    }
+
+    #[must_use]
+    pub fn open_region_count(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub const fn set_compression(&mut self, compression: RegionCompression) {
+        self.compression = compression;
+    }
+
+    /// Identifier storage mode used for chunks loaded from this store.
+    pub const fn set_identifier_mode(&mut self, mode: ChunkIdentifierMode) {
+        self.mode = mode;
+    }
+
+    #[must_use]
+    pub fn region_path(&self, pos: PosRegion) -> PathBuf {
+        self.root.join(format!("r.{}.{}.{}.nvmr", pos.x, pos.y, pos.z))
+    }
+
+    pub fn load_chunk(
+        &mut self,
+        pos:      PosChunk,
+        registry: &TileRegistry,
+    ) -> Result<Option<ChunkRecord>, RegionError> {
+        let (pos_region, idx) = PosRegion::from_chunk(pos);
+        let mode = self.mode;
+        let Some(region) = self.get_region(pos_region, false)? else { return Ok(None); };
+        let Some(data) = region.read(idx)? else { return Ok(None); };
+
+        let record = ChunkRecord::read_from(data.as_slice(), mode, registry)?;
+        if record.pos != pos {
+            return Err(RegionError::PositionMismatch{ expected: pos, found: record.pos });
+        }
+        Ok(Some(record))
+    }
+
+    pub fn save_chunk(
+        &mut self,
+        pos:      PosChunk,
+        tiles:    &ChunkStorage,
+        light:    Option<&LightStorageChunk>,
+        registry: &TileRegistry,
+    ) -> Result<(), RegionError> {
+        let mut data = Vec::new();
+        write_chunk_to(&mut data, pos, tiles, light, registry)?;
+
+        let (pos_region, idx) = PosRegion::from_chunk(pos);
+        let compression = self.compression;
+        let region = self.get_region(pos_region, true)?.unwrap();
+        region.write(idx, &data, compression)
+    }
+
+    pub fn remove_chunk(&mut self, pos: PosChunk) -> Result<(), RegionError> {
+        let (pos_region, idx) = PosRegion::from_chunk(pos);
+        match self.get_region(pos_region, false)? {
+            Some(region) => region.remove(idx),
+            None => Ok(()),
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), RegionError> {
+        for region in self.regions.values_mut() {
+            region.file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes and closes the region file, if it's open.
+    pub fn close_region(&mut self, pos: PosRegion) -> Result<(), RegionError> {
+        match self.regions.remove(&pos) {
+            Some(mut region) => region.file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes and closes every open region file.
+    pub fn close_all(&mut self) -> Result<(), RegionError> {
+        for (_, mut region) in self.regions.drain() {
+            region.file.flush()?;
+        }
+        Ok(())
+    }
+
+}
+
+impl RegionStore {
+
+    /// Loads the chunk into `world` (and `light` if provided) unless it's
+    /// already loaded. Returns whether the chunk was found on disk.
+    pub fn load_into(
+        &mut self,
+        pos:      PosChunk,
+        world:    &mut World,
+        light:    Option<&mut LightStorageWorld>,
+        registry: &TileRegistry,
+    ) -> Result<bool, RegionError> {
+        if world.is_chunk_loaded(pos) {
+            return Ok(true);
+        }
+
+        let Some(record) = self.load_chunk(pos, registry)? else { return Ok(false); };
+        world.insert_chunk(pos, record.tiles);
+        if let (Some(light), Some(light_chunk)) = (light, record.light) {
+            light.insert_chunk(pos, light_chunk);
+        }
+        Ok(true)
+    }
+
+    /// Returns the chunk from `world`, loading it from disk or creating an
+    /// empty chunk if it isn't loaded yet.
+    pub fn get_or_load_chunk<'a>(
+        &mut self,
+        pos:      PosChunk,
+        world:    &'a mut World,
+        registry: &TileRegistry,
+    ) -> Result<&'a mut ChunkStorage, RegionError> {
+        self.load_into(pos, world, None, registry)?;
+        Ok(world.get_or_create_chunk(pos))
+    }
+
+    /// Saves the chunk from `world` (and `light` if provided). Returns false
+    /// if the chunk isn't loaded.
+    pub fn save_from(
+        &mut self,
+        pos:      PosChunk,
+        world:    &World,
+        light:    Option<&LightStorageWorld>,
+        registry: &TileRegistry,
+    ) -> Result<bool, RegionError> {
+        let Some(tiles) = world.get_chunk(pos) else { return Ok(false); };
+        self.save_chunk(pos, tiles, light.and_then(|light| light.get_chunk(pos)), registry)?;
+        Ok(true)
+    }
+
+    /// Saves every chunk loaded in `world`.
+    pub fn save_all_from(
+        &mut self,
+        world:    &World,
+        light:    Option<&LightStorageWorld>,
+        registry: &TileRegistry,
+    ) -> Result<(), RegionError> {
+        for (pos, tiles) in world.iter_chunks() {
+            self.save_chunk(pos, tiles, light.and_then(|light| light.get_chunk(pos)), registry)?;
+        }
+        self.flush()
+    }
+
+}
+
+impl RegionStore {
+
+    fn get_region(&mut self, pos: PosRegion, create: bool) -> Result<Option<&mut RegionFile>, RegionError> {
+        let path = self.region_path(pos);
+        if !self.regions.contains_key(&pos) {
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+            while self.regions.len() >= self.max_open {
+                self.close_least_recent()?;
+            }
+        }
+
+        self.tick += 1;
+        let region = match self.regions.entry(pos) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => {
+                fs::create_dir_all(&self.root)?;
+                v.insert(OpenRegion{ file: RegionFile::open(path)?, last_used: 0 })
+            },
+        };
+        region.last_used = self.tick;
+        Ok(Some(&mut region.file))
+    }
+
+    fn close_least_recent(&mut self) -> Result<(), RegionError> {
+        let Some((&pos, _)) = self.regions.iter().min_by_key(|(_, region)| region.last_used) else { return Ok(()); };
+        self.close_region(pos)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tiles::TileDefinition, world::PosBlock};
+
+    use super::*;
+
+    #[test]
+    fn evicts_least_recent_region() {
+        let root = std::env::temp_dir().join(format!("nvm_region_store_{}", std::process::id()));
+        let mut registry = TileRegistry::new();
+        let tile = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+
+        let mut store = RegionStore::new(&root);
+        store.set_max_open(2);
+        let chunks = [PosChunk::new(0, 0, 0), PosChunk::new(8, 0, 0), PosChunk::new(-1, 0, 0), PosChunk::new(0, 9, -9)];
+        for (i, &pos) in chunks.iter().enumerate() {
+            let mut tiles = ChunkStorage::new_empty();
+            tiles.update(PosBlock::new(i as i16, 1, 2), tile, &registry);
+            store.save_chunk(pos, &tiles, None, &registry).unwrap();
+            assert!(store.open_region_count() <= 2);
+        }
+        store.close_all().unwrap();
+        assert_eq!(store.open_region_count(), 0);
+
+        for (i, &pos) in chunks.iter().enumerate() {
+            let record = store.load_chunk(pos, &registry).unwrap().unwrap();
+            assert_eq!(record.tiles.get(PosBlock::new(i as i16, 1, 2)), tile);
+            assert!(store.open_region_count() <= 2);
+        }
+        assert!(store.load_chunk(PosChunk::new(1, 0, 0), &registry).unwrap().is_none());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+}
@@ -5,5 +5,14 @@ pub struct TileIdentifier(u16);
 
 impl TileIdentifier {
     pub const DEFAULT: TileIdentifier = TileIdentifier(0);
-    pub const ONE: TileIdentifier = TileIdentifier(1);
+
+    #[must_use]
+    pub const fn from_raw(raw: u16) -> Self {
+        Self(raw)
+    }
+
+    #[must_use]
+    pub const fn to_raw(self) -> u16 {
+        self.0
+    }
 }
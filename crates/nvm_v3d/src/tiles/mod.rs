@@ -2,3 +2,15 @@
 
 mod identifier;
 pub use identifier::*;
+
+mod name;
+pub use name::*;
+
+mod state;
+pub use state::*;
+
+mod shape;
+pub use shape::*;
+
+mod registry;
+pub use registry::*;
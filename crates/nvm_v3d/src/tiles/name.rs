@@ -0,0 +1,55 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use core::fmt;
+
+/// Stable, namespaced tile name such as `core:stone`.
+///
+/// Both the namespace and the path must be non-empty and consist of lowercase
+/// ASCII letters, digits, `_`, `-`, `.` or `/`. The whole name is limited to
+/// `TileName::MAX_LEN` bytes, leaving room for the state properties appended
+/// to it in the `u16`-length state strings of the chunk format, see
+/// [`super::TileRegistry::get_state_string`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TileName(String);
+
+impl TileName {
+
+    pub const MAX_LEN: usize = u8::MAX as usize;
+
+    #[must_use]
+    pub fn new(name: &str) -> Option<Self> {
+        Self::is_valid(name).then(|| Self(name.to_owned()))
+    }
+
+    #[must_use]
+    pub fn is_valid(name: &str) -> bool {
+        let Some((namespace, path)) = name.split_once(':') else { return false; };
+        name.len() <= Self::MAX_LEN && Self::is_valid_part(namespace) && Self::is_valid_part(path)
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    #[must_use]
+    pub fn namespace(&self) -> &str {
+        self.0.split_once(':').unwrap().0
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &str {
+        self.0.split_once(':').unwrap().1
+    }
+
+    fn is_valid_part(part: &str) -> bool {
+        !part.is_empty() && part.bytes().all(|c| matches!(c, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b'/'))
+    }
+
+}
+
+impl fmt::Display for TileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
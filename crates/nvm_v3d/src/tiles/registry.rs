@@ -0,0 +1,461 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use core::fmt;
+use std::collections::HashMap;
+
+use crate::meshing::VisFace;
+
+use super::{TileHalf, TileIdentifier, TileName, TileProperty, TileShape, TileShapeState, TileStateValue, TILE_PROPERTY_FACING, TILE_PROPERTY_HALF};
+
+pub const TILE_NAME_AIR: &str = "core:air";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TileRegistryError {
+    InvalidName(String),
+    DuplicateName(TileName),
+    Full,
+}
+
+impl fmt::Display for TileRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name)   => write!(f, "invalid tile name {name:?}"),
+            Self::DuplicateName(name) => write!(f, "tile {name} is already registered"),
+            Self::Full                => write!(f, "tile registry is full"),
+        }
+    }
+}
+
+impl core::error::Error for TileRegistryError {}
+
+/// How a tile interacts with visibility and face culling.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TileOpacity {
+    /// Never rendered, never hides neighbouring faces.
+    #[default]
+    Empty,
+    /// Fully opaque cube, hides neighbouring faces.
+    Opaque,
+    /// Alpha-tested, eg. leaves.
+    Cutout,
+    /// Alpha-blended, eg. glass or water.
+    Translucent,
+}
+
+impl TileOpacity {
+
+    #[must_use]
+    pub const fn is_opaque(self) -> bool {
+        matches!(self, Self::Opaque)
+    }
+
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileDefinition {
+    /// Human readable name, see [`TileName`] for the stable identifier.
+    pub name:         String,
+    pub opacity:      TileOpacity,
+    /// Geometry of the tile, oriented by its state, see [`TileShape`].
+    pub shape:        TileShape,
+    /// Light lost when passing through this tile, per light channel. A value
+    /// of `u8::MAX` blocks the channel entirely.
+    pub transmission: [u8; 4],
+    /// Block light emitted by this tile, per colour channel.
+    pub emission:     [u8; 3],
+    /// Per-voxel state properties, see [`TileProperty`].
+    pub properties:   Vec<TileProperty>,
+}
+
+impl TileDefinition {
+
+    /// An opaque tile that blocks all light.
+    #[must_use]
+    pub fn new_opaque(name: impl Into<String>) -> Self {
+        Self{
+            name:         name.into(),
+            opacity:      TileOpacity::Opaque,
+            shape:        TileShape::Cube,
+            transmission: [u8::MAX; 4],
+            emission:     [0; 3],
+            properties:   Vec::new(),
+        }
+    }
+
+    /// An empty tile that transmits all light.
+    #[must_use]
+    pub fn new_empty(name: impl Into<String>) -> Self {
+        Self{
+            name:         name.into(),
+            opacity:      TileOpacity::Empty,
+            shape:        TileShape::Cube,
+            transmission: [0; 4],
+            emission:     [0; 3],
+            properties:   Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_property(mut self, property: TileProperty) -> Self {
+        self.properties.push(property);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_shape(mut self, shape: TileShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    /// Number of distinct states, the product of each property's value count.
+    #[must_use]
+    pub fn state_count(&self) -> usize {
+        self.properties.iter().map(TileProperty::len).product()
+    }
+
+    /// Returns the property and its stride within the state index.
+    fn find_property(&self, name: &str) -> Option<(&TileProperty, usize)> {
+        let mut stride = 1;
+        for property in &self.properties {
+            if property.name() == name {
+                return Some((property, stride));
+            }
+            stride *= property.len();
+        }
+        None
+    }
+
+}
+
+#[derive(Debug, Clone)]
+struct TileEntry {
+    name:       TileName,
+    definition: TileDefinition,
+    base:       TileIdentifier,
+}
+
+/// Maps each `TileIdentifier` to its definition. Tiles are registered under a
+/// stable [`TileName`], with runtime identifiers assigned in registration
+/// order, so identifiers must not be persisted without their names.
+///
+/// Tiles with properties reserve one consecutive identifier per state, the
+/// first being the default state returned by [`TileRegistry::register`].
+///
+/// `TileIdentifier::DEFAULT` is always registered as [`TILE_NAME_AIR`], and
+/// unregistered identifiers are treated as it.
+#[derive(Debug, Clone)]
+pub struct TileRegistry {
+    tiles:   Vec<TileEntry>,
+    states:  Vec<u16>,
+    shapes:  Vec<TileShapeState>,
+    by_name: HashMap<String, usize>,
+}
+
+impl Default for TileRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TileRegistry {
+
+    #[must_use]
+    pub fn new() -> Self {
+        let mut result = Self{
+            tiles:   Vec::new(),
+            states:  Vec::new(),
+            shapes:  Vec::new(),
+            by_name: HashMap::new(),
+        };
+        result.register(TILE_NAME_AIR, TileDefinition::new_empty("Air")).unwrap();
+        result
+    }
+
+    pub fn register(&mut self, name: &str, definition: TileDefinition) -> Result<TileIdentifier, TileRegistryError> {
+        let name = TileName::new(name).ok_or_else(|| TileRegistryError::InvalidName(name.to_owned()))?;
+        if self.by_name.contains_key(name.as_str()) {
+            return Err(TileRegistryError::DuplicateName(name));
+        }
+
+        let state_count = definition.state_count();
+        let base  = u16::try_from(self.states.len()).map_err(|_| TileRegistryError::Full)?;
+        let end   = self.states.len() + state_count;
+        let index = u16::try_from(self.tiles.len()).map_err(|_| TileRegistryError::Full)?;
+        if end > (u16::MAX as usize) + 1 {
+            return Err(TileRegistryError::Full);
+        }
+
+        let base = TileIdentifier::from_raw(base);
+        self.states.resize(end, index);
+        self.shapes.extend((0..state_count).map(|state| Self::resolve_shape(&definition, state)));
+        self.by_name.insert(name.as_str().to_owned(), self.tiles.len());
+        self.tiles.push(TileEntry{ name, definition, base });
+        Ok(base)
+    }
+
+    /// Returns the default state of the named tile.
+    #[must_use]
+    pub fn get_by_name(&self, name: &str) -> Option<TileIdentifier> {
+        self.by_name.get(name).map(|&i| self.tiles[i].base)
+    }
+
+    #[must_use]
+    pub fn get_name(&self, id: TileIdentifier) -> &TileName {
+        &self.get_entry(id).name
+    }
+
+    #[must_use]
+    pub fn get(&self, id: TileIdentifier) -> &TileDefinition {
+        &self.get_entry(id).definition
+    }
+
+    /// Number of identifiers in use, counting every state of every tile.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Iterates each registered tile with its default state.
+    pub fn iter(&self) -> impl Iterator<Item = (TileIdentifier, &TileName, &TileDefinition)> {
+        self.tiles.iter().map(|entry| (entry.base, &entry.name, &entry.definition))
+    }
+
+    /// Orients the definition's shape by the properties of one state, which
+    /// fall back to their defaults when missing.
+    fn resolve_shape(definition: &TileDefinition, state: usize) -> TileShapeState {
+        let get_value = |name: &str| definition.find_property(name).map(|(property, stride)| ((state / stride) % property.len()) as u8);
+        TileShapeState::new(
+            definition.shape,
+            get_value(TILE_PROPERTY_HALF  ).and_then(TileHalf::from_index).unwrap_or_default(),
+            get_value(TILE_PROPERTY_FACING).and_then(VisFace::from_index ).unwrap_or_default(),
+        )
+    }
+
+    fn get_entry(&self, id: TileIdentifier) -> &TileEntry {
+        &self.tiles[self.states.get(id.to_raw() as usize).copied().unwrap_or(0) as usize]
+    }
+
+}
+
+impl TileRegistry {
+
+    /// Returns the default state of the tile that `id` is a state of.
+    #[must_use]
+    pub fn get_base(&self, id: TileIdentifier) -> TileIdentifier {
+        self.get_entry(id).base
+    }
+
+    /// Returns the packed state index of `id`, relative to its default state.
+    #[must_use]
+    pub fn get_state_index(&self, id: TileIdentifier) -> usize {
+        (id.to_raw() - self.get_base(id).to_raw()) as usize
+    }
+
+    /// Returns the value index of the named property, or `None` if the tile
+    /// doesn't have it.
+    #[must_use]
+    pub fn get_property(&self, id: TileIdentifier, name: &str) -> Option<u8> {
+        let (property, stride) = self.get(id).find_property(name)?;
+        Some(((self.get_state_index(id) / stride) % property.len()) as u8)
+    }
+
+    /// Returns the state of `id` with the named property set to the value
+    /// index, or `None` if the tile doesn't have it or the value is out of
+    /// range.
+    #[must_use]
+    pub fn with_property(&self, id: TileIdentifier, name: &str, value: u8) -> Option<TileIdentifier> {
+        let (property, stride) = self.get(id).find_property(name)?;
+        if (value as usize) >= property.len() {
+            return None;
+        }
+
+        let state   = self.get_state_index(id);
+        let current = (state / stride) % property.len();
+        let state   = state - current*stride + (value as usize)*stride;
+        Some(TileIdentifier::from_raw(self.get_base(id).to_raw() + state as u16))
+    }
+
+    #[must_use]
+    pub fn get_property_value<T: TileStateValue>(&self, id: TileIdentifier, name: &str) -> Option<T> {
+        T::from_index(self.get_property(id, name)?)
+    }
+
+    #[must_use]
+    pub fn with_property_value<T: TileStateValue>(&self, id: TileIdentifier, name: &str, value: T) -> Option<TileIdentifier> {
+        self.with_property(id, name, value.to_index())
+    }
+
+    /// Formats the state as its name followed by its properties, such as
+    /// `core:stairs[facing=pos_x,half=top]`.
+    #[must_use]
+    pub fn get_state_string(&self, id: TileIdentifier) -> String {
+        let entry = self.get_entry(id);
+        let mut result = entry.name.as_str().to_owned();
+        let mut state  = self.get_state_index(id);
+        for (i, property) in entry.definition.properties.iter().enumerate() {
+            result.push(if i == 0 { '[' } else { ',' });
+            result.push_str(property.name());
+            result.push('=');
+            result.push_str(&property.values()[state % property.len()]);
+            state /= property.len();
+        }
+        if !entry.definition.properties.is_empty() {
+            result.push(']');
+        }
+        result
+    }
+
+    /// Parses a string produced by [`Self::get_state_string`]. Properties
+    /// the tile no longer has, or values it no longer accepts, are ignored
+    /// and left at their default.
+    #[must_use]
+    pub fn get_by_state_string(&self, state: &str) -> Option<TileIdentifier> {
+        let (name, properties) = match state.split_once('[') {
+            Some((name, properties)) => (name, properties.strip_suffix(']')?),
+            None => (state, ""),
+        };
+
+        let mut result = self.get_by_name(name)?;
+        for (key, value) in properties.split(',').filter_map(|v| v.split_once('=')) {
+            let Some((property, _)) = self.get(result).find_property(key) else { continue; };
+            let Some(value) = property.index_of(value) else { continue; };
+            result = self.with_property(result, key, value).unwrap_or(result);
+        }
+        Some(result)
+    }
+
+}
+
+impl TileRegistry {
+
+    #[must_use]
+    pub fn get_opacity(&self, id: TileIdentifier) -> TileOpacity {
+        self.get(id).opacity
+    }
+
+    #[must_use]
+    pub fn is_opaque(&self, id: TileIdentifier) -> bool {
+        self.get(id).opacity.is_opaque()
+    }
+
+    /// Opacity of the tile within the chunk visibility layers, which only
+    /// hold cubes. Other shapes are [`TileOpacity::Empty`] there, see
+    /// [`Self::get_shape_occlusion`] for the faces they cover.
+    #[must_use]
+    pub fn get_cube_opacity(&self, id: TileIdentifier) -> TileOpacity {
+        let definition = self.get(id);
+        match definition.shape {
+            TileShape::Cube => definition.opacity,
+            _               => TileOpacity::Empty,
+        }
+    }
+
+    /// Voxel faces covered by an opaque shape other than a cube, one bit per
+    /// [`VisFace`]. Cubes cull through the opaque visibility layer instead,
+    /// so report none here.
+    #[must_use]
+    pub fn get_shape_occlusion(&self, id: TileIdentifier) -> u8 {
+        let definition = self.get(id);
+        match definition.shape {
+            TileShape::Cube => 0,
+            _ if definition.opacity.is_opaque() => self.get_shape(id).occluded_faces(),
+            _ => 0,
+        }
+    }
+
+    #[must_use]
+    pub fn get_shape(&self, id: TileIdentifier) -> TileShapeState {
+        self.shapes.get(id.to_raw() as usize).copied().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn get_transmission(&self, id: TileIdentifier, channel: usize) -> u8 {
+        self.get(id).transmission[channel]
+    }
+
+    #[must_use]
+    pub fn get_emission(&self, id: TileIdentifier) -> [u8; 3] {
+        self.get(id).emission
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tiles::TILE_PROPERTY_FACING;
+
+    use super::*;
+
+    fn stairs() -> TileDefinition {
+        TileDefinition::new_opaque("Stairs")
+            .with_shape(TileShape::Stair)
+            .with_property(TileProperty::of::<VisFace>(TILE_PROPERTY_FACING))
+            .with_property(TileProperty::of::<TileHalf>(TILE_PROPERTY_HALF))
+    }
+
+    #[test]
+    fn register_and_look_up() {
+        let mut registry = TileRegistry::new();
+        let mut glass = TileDefinition::new_empty("Glass");
+        glass.opacity  = TileOpacity::Translucent;
+        glass.emission = [1, 2, 3];
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+        let glass = registry.register("test:glass", glass).unwrap();
+
+        assert_eq!(registry.get_by_name("test:stone"), Some(stone));
+        assert_eq!(registry.get_name(glass).as_str(), "test:glass");
+        assert!(registry.is_opaque(stone));
+        assert_eq!(registry.get_opacity(glass), TileOpacity::Translucent);
+        assert_eq!(registry.get_transmission(stone, 0), u8::MAX);
+        assert_eq!(registry.get_emission(glass), [1, 2, 3]);
+
+        // Unregistered identifiers read as air
+        let unknown = TileIdentifier::from_raw(1000);
+        assert_eq!(registry.get_name(unknown).as_str(), TILE_NAME_AIR);
+        assert_eq!(registry.get_opacity(unknown), TileOpacity::Empty);
+
+        assert!(matches!(registry.register("test:stone", TileDefinition::new_opaque("Stone")), Err(TileRegistryError::DuplicateName(_))));
+        assert!(matches!(registry.register("no namespace", TileDefinition::new_opaque("Stone")), Err(TileRegistryError::InvalidName(_))));
+    }
+
+    #[test]
+    fn state_properties() {
+        let mut registry = TileRegistry::new();
+        let stairs = registry.register("test:stairs", stairs()).unwrap();
+        let stone  = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+        assert_eq!(registry.len(), 1 + 12 + 1);
+        assert_eq!(stone.to_raw(), 13);
+
+        let state = registry.with_property_value(stairs, TILE_PROPERTY_FACING, VisFace::NegZ).unwrap();
+        let state = registry.with_property_value(state, TILE_PROPERTY_HALF, TileHalf::Top).unwrap();
+        assert_eq!(registry.get_base(state), stairs);
+        assert_eq!(registry.get_property_value(state, TILE_PROPERTY_FACING), Some(VisFace::NegZ));
+        assert_eq!(registry.get_property_value(state, TILE_PROPERTY_HALF), Some(TileHalf::Top));
+        assert_eq!(registry.with_property(state, TILE_PROPERTY_HALF, 2), None);
+        assert_eq!(registry.get_property(stone, TILE_PROPERTY_HALF), None);
+
+        let string = registry.get_state_string(state);
+        assert_eq!(string, "test:stairs[facing=neg_z,half=top]");
+        assert_eq!(registry.get_by_state_string(&string), Some(state));
+        assert_eq!(registry.get_by_state_string("test:stairs[half=top,colour=red]"), registry.with_property(stairs, TILE_PROPERTY_HALF, 1));
+        assert_eq!(registry.get_by_state_string("test:missing"), None);
+    }
+
+    #[test]
+    fn shapes_follow_state() {
+        let mut registry = TileRegistry::new();
+        let stairs = registry.register("test:stairs", stairs()).unwrap();
+        let state  = registry.with_property_value(stairs, TILE_PROPERTY_FACING, VisFace::PosZ).unwrap();
+        let state  = registry.with_property_value(state, TILE_PROPERTY_HALF, TileHalf::Top).unwrap();
+
+        assert_eq!(registry.get_cube_opacity(state), TileOpacity::Empty);
+        assert_eq!(registry.get_shape(state), TileShapeState::new(TileShape::Stair, TileHalf::Top, VisFace::PosZ));
+        assert_eq!(registry.get_shape_occlusion(state), (1 << VisFace::NegY as u8) | (1 << VisFace::PosZ as u8));
+    }
+
+}
@@ -0,0 +1,133 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use crate::meshing::VisFace;
+
+use super::TileHalf;
+
+/// Property read by [`TileShape::Slab`] and [`TileShape::Stair`] to pick the
+/// half they fill, holding a [`TileHalf`].
+pub const TILE_PROPERTY_HALF: &str = "half";
+
+/// Property read by [`TileShape::Stair`] to pick the voxel face covered by
+/// the back of its step, holding a [`VisFace`].
+pub const TILE_PROPERTY_FACING: &str = "facing";
+
+/// Geometry of a tile. Anything other than [`TileShape::Cube`] is left out of
+/// the chunk visibility layers and meshed by [`crate::meshing::mesh_chunk_shapes`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TileShape {
+    #[default]
+    Cube,
+    /// Half-height box filling the bottom or top, see [`TILE_PROPERTY_HALF`].
+    Slab,
+    /// A slab with a quarter box on the other half, on the side given by
+    /// [`TILE_PROPERTY_FACING`].
+    Stair,
+    /// Two double-sided quads crossing diagonally, eg. grass or flowers.
+    Cross,
+}
+
+/// Axis aligned box within a voxel, in half-voxel units from `0` to `2`.
+///
+/// Sides are named by the voxel face they lie on, which like the cube faces
+/// puts the positive faces at `0` and the negative faces at `2`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShapeBox {
+    pub min: [u8; 3],
+    pub max: [u8; 3],
+}
+
+impl ShapeBox {
+
+    pub const EMPTY: Self = Self::new([0; 3], [0; 3]);
+    pub const FULL:  Self = Self::new([0; 3], [2; 3]);
+
+    #[must_use]
+    pub const fn new(min: [u8; 3], max: [u8; 3]) -> Self {
+        Self{ min, max }
+    }
+
+    /// Clips this box to the half of the voxel on the given side.
+    #[must_use]
+    pub const fn clip_to_half(self, face: VisFace) -> Self {
+        let axis = face.axis() as usize;
+        let (mut min, mut max) = (self.min, self.max);
+        match face {
+            VisFace::PosX | VisFace::PosY | VisFace::PosZ => if max[axis] > 1 { max[axis] = 1; },
+            VisFace::NegX | VisFace::NegY | VisFace::NegZ => if min[axis] < 1 { min[axis] = 1; },
+        }
+        Self{ min, max }
+    }
+
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.min[0] >= self.max[0] || self.min[1] >= self.max[1] || self.min[2] >= self.max[2]
+    }
+
+    /// Whether the side of this box facing `face` lies on the voxel's border.
+    #[must_use]
+    pub const fn touches_border(self, face: VisFace) -> bool {
+        let axis = face.axis() as usize;
+        match face {
+            VisFace::PosX | VisFace::PosY | VisFace::PosZ => self.min[axis] == 0,
+            VisFace::NegX | VisFace::NegY | VisFace::NegZ => self.max[axis] == 2,
+        }
+    }
+
+}
+
+/// A [`TileShape`] oriented by the state of its tile.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileShapeState {
+    pub shape:  TileShape,
+    pub half:   TileHalf,
+    pub facing: VisFace,
+}
+
+impl TileShapeState {
+
+    #[must_use]
+    pub const fn new(shape: TileShape, half: TileHalf, facing: VisFace) -> Self {
+        Self{ shape, half, facing }
+    }
+
+    /// Faces of the voxel this shape covers entirely, one bit per
+    /// [`VisFace`]. Neighbouring faces against them are hidden.
+    #[must_use]
+    pub const fn occluded_faces(self) -> u8 {
+        let half = 1 << (self.half_face() as u8);
+        match self.shape {
+            TileShape::Cube  => 0x3F,
+            TileShape::Slab  => half,
+            TileShape::Stair => half | (1 << (self.facing as u8)),
+            TileShape::Cross => 0,
+        }
+    }
+
+    #[must_use]
+    pub const fn occludes(self, face: VisFace) -> bool {
+        self.occluded_faces() & (1 << (face as u8)) != 0
+    }
+
+    /// Boxes making up the shape, unused ones being [`ShapeBox::EMPTY`].
+    /// Crosses have no boxes, their quads span the whole voxel.
+    #[must_use]
+    pub const fn boxes(self) -> [ShapeBox; 2] {
+        let slab = ShapeBox::FULL.clip_to_half(self.half_face());
+        match self.shape {
+            TileShape::Cube  => [ShapeBox::FULL, ShapeBox::EMPTY],
+            TileShape::Slab  => [slab, ShapeBox::EMPTY],
+            TileShape::Stair => [slab, ShapeBox::FULL.clip_to_half(self.half_face().reverse()).clip_to_half(self.facing)],
+            TileShape::Cross => [ShapeBox::EMPTY, ShapeBox::EMPTY],
+        }
+    }
+
+    /// Voxel face the slab part of the shape rests against.
+    const fn half_face(self) -> VisFace {
+        match self.half {
+            TileHalf::Bottom => VisFace::PosY,
+            TileHalf::Top    => VisFace::NegY,
+        }
+    }
+
+}
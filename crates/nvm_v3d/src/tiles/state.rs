@@ -0,0 +1,121 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use crate::meshing::VisFace;
+
+/// A value type that can be stored in a [`TileProperty`].
+pub trait TileStateValue: Copy {
+    /// Names of each value, in index order, as used in saved chunks.
+    const VALUES: &'static [&'static str];
+
+    fn to_index(self) -> u8;
+
+    fn from_index(idx: u8) -> Option<Self>;
+}
+
+impl TileStateValue for bool {
+    const VALUES: &'static [&'static str] = &["false", "true"];
+
+    fn to_index(self) -> u8 {
+        self as u8
+    }
+
+    fn from_index(idx: u8) -> Option<Self> {
+        match idx {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl TileStateValue for VisFace {
+    const VALUES: &'static [&'static str] = &["pos_x", "pos_y", "pos_z", "neg_x", "neg_y", "neg_z"];
+
+    fn to_index(self) -> u8 {
+        self as u8
+    }
+
+    fn from_index(idx: u8) -> Option<Self> {
+        Self::from_raw(idx as u16)
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TileHalf {
+    #[default]
+    Bottom = 0,
+    Top    = 1,
+}
+
+impl TileStateValue for TileHalf {
+    const VALUES: &'static [&'static str] = &["bottom", "top"];
+
+    fn to_index(self) -> u8 {
+        self as u8
+    }
+
+    fn from_index(idx: u8) -> Option<Self> {
+        match idx {
+            0 => Some(Self::Bottom),
+            1 => Some(Self::Top),
+            _ => None,
+        }
+    }
+}
+
+/// A named per-voxel property of a tile, such as `facing` or `half`.
+///
+/// A tile's properties are packed into a state index, with the first property
+/// in the least significant position. Each state occupies its own
+/// `TileIdentifier`, so states are stored, palette-compressed and compared
+/// exactly like plain tiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileProperty {
+    name:   String,
+    values: Box<[String]>,
+}
+
+impl TileProperty {
+
+    #[must_use]
+    pub fn new(name: impl Into<String>, values: &[&str]) -> Self {
+        assert!(!values.is_empty() && u8::try_from(values.len()).is_ok(), "Tile property must have between 1 and 255 values");
+        Self{
+            name:   name.into(),
+            values: values.iter().map(|&v| v.to_owned()).collect(),
+        }
+    }
+
+    /// Creates a property holding values of `T`.
+    #[must_use]
+    pub fn of<T: TileStateValue>(name: impl Into<String>) -> Self {
+        Self::new(name, T::VALUES)
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn values(&self) -> &[String] {
+        &self.values
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    #[must_use]
+    pub fn index_of(&self, value: &str) -> Option<u8> {
+        self.values.iter().position(|v| v == value).map(|v| v as u8)
+    }
+
+}
@@ -0,0 +1,170 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use crate::{meshing::{BitPlane, FaceOcclusionProvider, FaceTileProvider, FaceVisibilityProvider, VisFace}, tiles::{TileIdentifier, TileOpacity}};
+
+use super::{ChunkStorage, PosBlock, PosChunk, World, CHUNK_SIZE};
+
+/// How chunk borders facing an unloaded neighbour are culled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnloadedChunkMode {
+    /// Treat the neighbour as empty, emitting every border face.
+    #[default]
+    Empty,
+    /// Treat the neighbour as solid, emitting no border faces.
+    Solid,
+}
+
+/// A chunk along with its six face neighbours, culling border faces against
+/// the neighbouring chunks rather than treating them as empty.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkNeighbourhood<'a, const SIZE: usize = CHUNK_SIZE> {
+    chunk:      &'a ChunkStorage<SIZE>,
+    /// Neighbouring chunks, indexed by the [`VisFace`] pointing towards them.
+    neighbours: [Option<&'a ChunkStorage<SIZE>>; 6],
+    unloaded:   UnloadedChunkMode,
+}
+
+impl<'a, const SIZE: usize> ChunkNeighbourhood<'a, SIZE> {
+
+    #[must_use]
+    pub const fn new(chunk: &'a ChunkStorage<SIZE>, neighbours: [Option<&'a ChunkStorage<SIZE>>; 6], unloaded: UnloadedChunkMode) -> Self {
+        Self{ chunk, neighbours, unloaded }
+    }
+
+    #[must_use]
+    pub const fn chunk(&self) -> &'a ChunkStorage<SIZE> {
+        self.chunk
+    }
+
+    #[must_use]
+    pub const fn neighbour(&self, direction: VisFace) -> Option<&'a ChunkStorage<SIZE>> {
+        self.neighbours[direction as usize]
+    }
+
+    #[must_use]
+    pub const fn unloaded_mode(&self) -> UnloadedChunkMode {
+        self.unloaded
+    }
+
+}
+
+impl<'a> ChunkNeighbourhood<'a> {
+
+    /// Gathers the chunk at `pos` and its neighbours, or `None` if the chunk
+    /// itself isn't loaded. Neighbours wrap at the edge of the coordinate
+    /// range, like [`PosChunk::neighbour`].
+    #[must_use]
+    pub fn from_world(world: &'a World, pos: PosChunk, unloaded: UnloadedChunkMode) -> Option<Self> {
+        let chunk = world.get_chunk(pos)?;
+        let neighbours = VisFace::ALL.map(|face| world.get_chunk(pos.neighbour(face)));
+        Some(Self::new(chunk, neighbours, unloaded))
+    }
+
+}
+
+impl<const SIZE: usize> FaceVisibilityProvider for ChunkNeighbourhood<'_, SIZE> {
+
+    const CHUNK_SIZE: usize = SIZE;
+
+    fn get_face_visibility_plane(&self, chunk: [usize; 3], face: VisFace, layer: u32) -> BitPlane {
+        self.get_face_visibility_plane_for(chunk, face, layer, TileOpacity::Opaque)
+    }
+
+    fn get_face_visibility_plane_for(&self, chunk: [usize; 3], face: VisFace, layer: u32, opacity: TileOpacity) -> BitPlane {
+        // Positive faces are culled by the voxel before them, negative faces by the one after
+        let border = match (self.neighbour(face.reverse()), self.unloaded) {
+            (Some(neighbour), _)              => neighbour.get_border_plane(self.chunk, chunk, face, opacity),
+            (None, UnloadedChunkMode::Empty)  => BitPlane::DEFAULT,
+            (None, UnloadedChunkMode::Solid)  => BitPlane::FULL,
+        };
+        self.chunk.get_face_visibility_plane_with_border(chunk, face, layer, opacity, border)
+    }
+
+}
+
+impl<const SIZE: usize> FaceTileProvider for ChunkNeighbourhood<'_, SIZE> {
+
+    fn get_face_tile(&self, pos: [usize; 3]) -> TileIdentifier {
+        self.chunk.get_face_tile(pos)
+    }
+
+}
+
+impl<const SIZE: usize> FaceOcclusionProvider for ChunkNeighbourhood<'_, SIZE> {
+
+    /// Positions past the chunk along a single axis are read from that face
+    /// neighbour. Positions past an edge or corner of the chunk aren't
+    /// covered by the neighbourhood and are treated as unloaded.
+    fn is_face_occluder(&self, pos: [isize; 3]) -> bool {
+        let size = SIZE as isize;
+        let mut direction = None;
+        for (axis, &v) in pos.iter().enumerate() {
+            let outside = match v {
+                v if v < 0     => Some(axis + 3),
+                v if v >= size => Some(axis),
+                _ => continue,
+            };
+            if direction.is_some() {
+                return self.unloaded == UnloadedChunkMode::Solid;
+            }
+            direction = outside;
+        }
+
+        let chunk = match direction {
+            None => self.chunk,
+            Some(direction) => match self.neighbours[direction] {
+                Some(neighbour) => neighbour,
+                None => return self.unloaded == UnloadedChunkMode::Solid,
+            },
+        };
+        let pos = pos.map(|v| v.rem_euclid(size) as i16);
+        chunk.is_opaque(PosBlock::new(pos[0], pos[1], pos[2]))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{meshing::ChunkMesh, tiles::{TileDefinition, TileRegistry}};
+
+    use super::*;
+
+    #[test]
+    fn culls_borders_of_sized_chunks() {
+        let mut registry = TileRegistry::new();
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+
+        let mut chunk = <ChunkStorage<16>>::new_empty();
+        chunk.update(PosBlock::new(15, 3, 4), stone, &registry);
+        let mut neighbour = <ChunkStorage<16>>::new_empty();
+        neighbour.update(PosBlock::new(0, 3, 4), stone, &registry);
+
+        let mut neighbours = [None; 6];
+        neighbours[VisFace::PosX as usize] = Some(&neighbour);
+        for (neighbours, faces) in [([None; 6], 6), (neighbours, 5)] {
+            let neighbourhood = ChunkNeighbourhood::new(&chunk, neighbours, UnloadedChunkMode::Empty);
+            let mut mesh = ChunkMesh::default();
+            mesh.build(&neighbourhood);
+            assert_eq!(mesh.faces().len(), faces);
+            assert_eq!(neighbourhood.is_face_occluder([16, 3, 4]), faces == 5);
+        }
+    }
+
+    #[test]
+    fn from_world_wraps_at_coordinate_edge() {
+        let mut world = World::default();
+        let (max, min) = (PosChunk::new(i16::MAX, 0, 0), PosChunk::new(i16::MIN, 0, 0));
+        world.get_or_create_chunk(max);
+        world.get_or_create_chunk(min);
+
+        let neighbourhood = ChunkNeighbourhood::from_world(&world, max, UnloadedChunkMode::Empty).unwrap();
+        for face in VisFace::ALL {
+            assert_eq!(neighbourhood.neighbour(face).is_some(), face == VisFace::PosX, "{face:?}");
+        }
+        let neighbourhood = ChunkNeighbourhood::from_world(&world, min, UnloadedChunkMode::Empty).unwrap();
+        for face in VisFace::ALL {
+            assert_eq!(neighbourhood.neighbour(face).is_some(), face == VisFace::NegX, "{face:?}");
+        }
+    }
+
+}
@@ -1,63 +1,629 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //s
 
-use crate::{meshing::{BitPlane, VisAxis, VisFace, FaceVisibilityProvider}, tiles::TileIdentifier};
-use super::PosBlock;
+use std::borrow::Cow;
 
-pub const CHUNK_COORD_BITS:  usize = 5;
-pub const CHUNK_SIZE:        usize = 1 << CHUNK_COORD_BITS;
-pub const CHUNK_LENGTH:      usize = CHUNK_SIZE*CHUNK_SIZE*CHUNK_SIZE;
+use crate::{meshing::{BitPlane, VisAxis, VisFace, FaceOcclusionProvider, FaceTileProvider, FaceVisibilityProvider}, tiles::{TileIdentifier, TileOpacity, TileRegistry, TileStateValue}};
+use super::{DirtySubChunks, IdentifierPalette, PosBlock};
+
+/// Default chunk size, used by [`World`](super::World) and wherever a size
+/// isn't given.
+pub const CHUNK_SIZE:        usize = 32;
+pub const CHUNK_COORD_BITS:  usize = ChunkDimensions::<CHUNK_SIZE>::COORD_BITS;
+pub const CHUNK_LENGTH:      usize = ChunkDimensions::<CHUNK_SIZE>::LENGTH;
 pub const CHUNK_LENGTH_MASK: usize = CHUNK_LENGTH - 1;
 
 pub const CHUNK_SOLID_MASK: usize = CHUNK_SIZE*CHUNK_SIZE;
 
-pub const CHUNK_VIS_SIZE:   usize = CHUNK_SIZE/8;
-pub const CHUNK_VIS_LENGTH: usize = CHUNK_VIS_SIZE * CHUNK_VIS_SIZE * CHUNK_VIS_SIZE;
+pub const CHUNK_VIS_SIZE:   usize = ChunkDimensions::<CHUNK_SIZE>::VIS_SIZE;
+pub const CHUNK_VIS_LENGTH: usize = ChunkDimensions::<CHUNK_SIZE>::VIS_LENGTH;
+
+/// Derived dimensions of chunks `SIZE` voxels across. Sizes must be a power
+/// of two from 8 to 64, so chunks split into whole 8x8x8 sub-chunks and a row
+/// of voxels fits in a `u64`.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkDimensions<const SIZE: usize>;
+
+impl<const SIZE: usize> ChunkDimensions<SIZE> {
+
+    pub const COORD_BITS: usize = {
+        assert!(SIZE.is_power_of_two() && 8 <= SIZE && SIZE <= 64, "Chunk size must be a power of two from 8 to 64");
+        SIZE.trailing_zeros() as usize
+    };
+
+    pub const LENGTH:     usize = 1 << (3*Self::COORD_BITS);
+    pub const VIS_SIZE:   usize = SIZE/8;
+    pub const VIS_LENGTH: usize = Self::VIS_SIZE*Self::VIS_SIZE*Self::VIS_SIZE;
+
+    /// Visibility planes of one layer, one per 8x8 column, layer and axis.
+    const VIS_PLANES: usize = 3*Self::VIS_SIZE*Self::VIS_SIZE*SIZE;
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChunkIdentifierMode {
+    /// One `TileIdentifier` per voxel, fastest access.
+    Direct,
+    /// Bit-packed indices into a per-chunk palette, see [`IdentifierPalette`].
+    Palette,
+}
+
+#[derive(Debug)]
+enum ChunkIdentifiers<const SIZE: usize> {
+    Uniform(TileIdentifier),
+    Direct(Box<[TileIdentifier]>),
+    Palette(IdentifierPalette<SIZE>),
+}
+
+#[derive(Debug)]
+enum ChunkVisibility<const SIZE: usize> {
+    Uniform(bool),
+    Planes(Box<[BitPlane]>),
+}
+
+const VIS_LAYER_OPAQUE:      usize = 0;
+const VIS_LAYER_CUTOUT:      usize = 1;
+const VIS_LAYER_TRANSLUCENT: usize = 2;
+const VIS_LAYER_COUNT:       usize = 3;
+
+impl<const SIZE: usize> ChunkVisibility<SIZE> {
+
+    const VIS_SIZE:   usize = ChunkDimensions::<SIZE>::VIS_SIZE;
+    const VIS_PLANES: usize = ChunkDimensions::<SIZE>::VIS_PLANES;
+
+    const fn get(&self, idx: usize) -> BitPlane {
+        match self {
+            Self::Uniform(false)   => BitPlane::DEFAULT,
+            Self::Uniform(true)    => BitPlane::FULL,
+            Self::Planes(vis_data) => vis_data[idx],
+        }
+    }
+
+    fn set(&mut self, pos: PosBlock<SIZE>, solid: bool) {
+        let vis_data = match self {
+            Self::Uniform(current) if *current == solid => return,
+            Self::Uniform(current) => {
+                let fill = if *current { BitPlane::FULL } else { BitPlane::DEFAULT };
+                *self = Self::Planes(vec![fill; Self::VIS_PLANES].into_boxed_slice());
+                let Self::Planes(vis_data) = self else { unreachable!() };
+                vis_data
+            },
+            Self::Planes(vis_data) => vis_data,
+        };
+
+        let [x_idx, x_vis] = ChunkStorage::<SIZE>::get_vis_idx(pos, VisAxis::X);
+        if vis_data[x_vis].get(x_idx as u32) != solid {
+            let [y_idx, y_vis] = ChunkStorage::<SIZE>::get_vis_idx(pos, VisAxis::Y);
+            let [z_idx, z_vis] = ChunkStorage::<SIZE>::get_vis_idx(pos, VisAxis::Z);
+            vis_data[x_vis].set(x_idx as u32, solid);
+            vis_data[y_vis].set(y_idx as u32, solid);
+            vis_data[z_vis].set(z_idx as u32, solid);
+        }
+    }
+
+    fn shrink(&mut self) {
+        let Self::Planes(vis_data) = self else { return; };
+        if vis_data.iter().all(|&plane| plane == BitPlane::DEFAULT) {
+            *self = Self::Uniform(false);
+        } else if vis_data.iter().all(|&plane| plane == BitPlane::FULL) {
+            *self = Self::Uniform(true);
+        }
+    }
+
+    const fn heap_size(&self) -> usize {
+        match self {
+            Self::Uniform(_) => 0,
+            Self::Planes(_)  => Self::VIS_PLANES*size_of::<BitPlane>(),
+        }
+    }
+
+    /// Rebuilds every plane from rows of voxels along x, indexed by
+    /// `y + z*SIZE`, reusing the current planes if allocated.
+    fn rebuild(&mut self, rows: &[u64]) {
+        let full = u64::MAX >> (64 - SIZE);
+        if rows.iter().all(|&row| row == 0) {
+            *self = Self::Uniform(false);
+            return;
+        } else if rows.iter().all(|&row| row == full) {
+            *self = Self::Uniform(true);
+            return;
+        }
+
+        if let Self::Uniform(_) = self {
+            *self = Self::Planes(vec![BitPlane::DEFAULT; Self::VIS_PLANES].into_boxed_slice());
+        }
+        let Self::Planes(vis_data) = self else { unreachable!() };
+
+        // Bits `x..x+8` of the row at `y`, `z`
+        let byte = |x: usize, y: usize, z: usize| (rows[y + z*SIZE] >> (x*8)) & 0xFF;
+        let plane_idx = |axis: VisAxis, x: usize, y: usize, layer: usize| {
+            (axis as usize)*Self::VIS_SIZE*Self::VIS_SIZE*SIZE + x*SIZE + y*Self::VIS_SIZE*SIZE + layer
+        };
+
+        for cx in 0..Self::VIS_SIZE {
+            for cy in 0..Self::VIS_SIZE {
+                for layer in 0..SIZE {
+                    // Z planes hold x, y and Y planes hold x, z, both read straight from the rows
+                    let mut plane_z = 0;
+                    let mut plane_y = 0;
+                    for j in 0..8 {
+                        plane_z |= byte(cx, cy*8 + j, layer) << (8*j);
+                        plane_y |= byte(cx, layer, cy*8 + j) << (8*j);
+                    }
+                    vis_data[plane_idx(VisAxis::Z, cx, cy, layer)] = BitPlane::from_raw(plane_z);
+                    vis_data[plane_idx(VisAxis::Y, cx, cy, layer)] = BitPlane::from_raw(plane_y);
+                }
+            }
+        }
+
+        // X planes hold z, y, so each 8x8 block of x and z is transposed
+        for cz in 0..Self::VIS_SIZE {
+            for cy in 0..Self::VIS_SIZE {
+                for cx in 0..Self::VIS_SIZE {
+                    let mut planes = [0; 8];
+                    for j in 0..8 {
+                        let mut block = 0;
+                        for k in 0..8 {
+                            block |= byte(cx, cy*8 + j, cz*8 + k) << (8*k);
+                        }
+                        let block = transpose_bits_8x8(block);
+                        for (i, plane) in planes.iter_mut().enumerate() {
+                            *plane |= ((block >> (8*i)) & 0xFF) << (8*j);
+                        }
+                    }
+                    for (i, plane) in planes.into_iter().enumerate() {
+                        vis_data[plane_idx(VisAxis::X, cz, cy, cx*8 + i)] = BitPlane::from_raw(plane);
+                    }
+                }
+            }
+        }
+    }
 
-pub struct ChunkStorage {
-    identifiers: Box<[TileIdentifier; CHUNK_LENGTH    ]>,
-    vis_data:    Box<[BitPlane; 3*CHUNK_VIS_SIZE*CHUNK_VIS_SIZE*CHUNK_SIZE]>,
 }
 
-impl ChunkStorage {
+/// Transposes an 8x8 bit matrix stored row by row, see Hacker's Delight 7-3.
+const fn transpose_bits_8x8(mut x: u64) -> u64 {
+    let t = (x ^ (x >>  7)) & 0x00AA_00AA_00AA_00AA;
+    x = x ^ t ^ (t <<  7);
+    let t = (x ^ (x >> 14)) & 0x0000_CCCC_0000_CCCC;
+    x = x ^ t ^ (t << 14);
+    let t = (x ^ (x >> 28)) & 0x0000_0000_F0F0_F0F0;
+    x ^ t ^ (t << 28)
+}
+
+/// Tile storage for a single chunk. Chunks made of a single identifier are
+/// stored without any heap allocation, and switch to full
+/// storage in the configured [`ChunkIdentifierMode`] on the first differing
+/// update.
+///
+/// Visibility is tracked separately for opaque, cutout and translucent tiles,
+/// each layer only allocating planes once the chunk contains such a tile.
+/// The sides covered by opaque shapes other than cubes are tracked per face
+/// the same way, so cube faces against them are culled too, see
+/// [`TileRegistry::get_shape_occlusion`].
+///
+/// Edits mark the sub-chunks they affect as dirty, see [`Self::drain_dirty`].
+/// New chunks start entirely dirty.
+///
+/// Chunks are `SIZE` voxels across, see [`ChunkDimensions`] for the sizes
+/// supported. Other sizes are limited to the storage itself,
+/// [`ChunkNeighbourhood`](super::ChunkNeighbourhood) and the meshers.
+/// [`World`](super::World), lighting, persistence and the region types such
+/// as [`RegionWorld`](super::RegionWorld) only handle [`CHUNK_SIZE`] chunks.
+/// Ambient occlusion only fits the vertex layout of chunks up to 32 across,
+/// meshing larger chunks with it fails to compile.
+#[derive(Debug)]
+pub struct ChunkStorage<const SIZE: usize = CHUNK_SIZE> {
+    mode:        ChunkIdentifierMode,
+    identifiers: ChunkIdentifiers<SIZE>,
+    vis_data:    [ChunkVisibility<SIZE>; VIS_LAYER_COUNT],
+    occluders:   [ChunkVisibility<SIZE>; 6],
+    dirty:       DirtySubChunks<SIZE>,
+}
+
+impl<const SIZE: usize> ChunkStorage<SIZE> {
+
+    const LENGTH:   usize = ChunkDimensions::<SIZE>::LENGTH;
+    const VIS_SIZE: usize = ChunkDimensions::<SIZE>::VIS_SIZE;
+
+    #[must_use]
+    pub const fn new_empty() -> Self {
+        Self::new_empty_with_mode(ChunkIdentifierMode::Palette)
+    }
+
+    #[must_use]
+    pub const fn new_empty_with_mode(mode: ChunkIdentifierMode) -> Self {
+        Self::new_uniform_raw(TileIdentifier::DEFAULT, TileOpacity::Empty, 0, mode)
+    }
 
     #[must_use]
-    pub fn new_empty() -> Self {
+    pub fn new_uniform(id: TileIdentifier, registry: &TileRegistry) -> Self {
+        Self::new_uniform_with_mode(id, ChunkIdentifierMode::Palette, registry)
+    }
+
+    #[must_use]
+    pub fn new_uniform_with_mode(id: TileIdentifier, mode: ChunkIdentifierMode, registry: &TileRegistry) -> Self {
+        Self::new_uniform_raw(id, registry.get_cube_opacity(id), registry.get_shape_occlusion(id), mode)
+    }
+
+    const fn new_uniform_raw(id: TileIdentifier, opacity: TileOpacity, occlusion: u8, mode: ChunkIdentifierMode) -> Self {
         Self{
-            identifiers: vec![TileIdentifier::DEFAULT; CHUNK_LENGTH].into_boxed_slice().try_into().unwrap(),
-            vis_data:    vec![BitPlane::DEFAULT; 3*CHUNK_VIS_SIZE*CHUNK_VIS_SIZE*CHUNK_SIZE].into_boxed_slice().try_into().unwrap(),
+            mode,
+            identifiers: ChunkIdentifiers::Uniform(id),
+            vis_data:    [
+                ChunkVisibility::Uniform(matches!(opacity, TileOpacity::Opaque)),
+                ChunkVisibility::Uniform(matches!(opacity, TileOpacity::Cutout)),
+                ChunkVisibility::Uniform(matches!(opacity, TileOpacity::Translucent)),
+            ],
+            occluders:   Self::uniform_occluders(occlusion),
+            dirty:       DirtySubChunks::ALL,
         }
     }
 
-    pub fn update(&mut self, pos: PosBlock, id: TileIdentifier, solid: bool) {
-        self.identifiers[pos.to_idx()] = id;
-        let [x_idx, x_vis] = Self::get_vis_idx(pos, VisAxis::X);
-        if self.vis_data[x_vis].get(x_idx as u32) != solid {
-            let [y_idx, y_vis] = Self::get_vis_idx(pos, VisAxis::Y);
-            let [z_idx, z_vis] = Self::get_vis_idx(pos, VisAxis::Z);
-            self.vis_data[x_vis].set(x_idx as u32, solid);
-            self.vis_data[y_vis].set(y_idx as u32, solid);
-            self.vis_data[z_vis].set(z_idx as u32, solid);
+    /// Builds a chunk from palette-packed identifiers, rebuilding the
+    /// visibility planes from the registry.
+    #[must_use]
+    pub fn from_identifier_palette(
+        palette:  IdentifierPalette<SIZE>,
+        mode:     ChunkIdentifierMode,
+        registry: &TileRegistry,
+    ) -> Self {
+        if palette.palette().len() == 1 {
+            return Self::new_uniform_with_mode(palette.palette()[0], mode, registry);
+        }
+
+        let mut result = Self::new_empty_with_mode(mode);
+        result.identifiers = ChunkIdentifiers::Palette(palette);
+        result.rebuild_visibility(registry);
+        result.set_identifier_mode(mode);
+        result
+    }
+
+    /// Builds a chunk from one identifier per voxel, in [`PosBlock::to_idx`]
+    /// order, building the visibility planes in bulk.
+    #[must_use]
+    pub fn from_identifiers(
+        identifiers: &[TileIdentifier],
+        mode:        ChunkIdentifierMode,
+        registry:    &TileRegistry,
+    ) -> Self {
+        let mut result = Self::new_empty_with_mode(mode);
+        result.set_identifiers(identifiers, registry);
+        result
+    }
+
+    /// Replaces every voxel, rebuilding the visibility planes in bulk. This
+    /// is much faster than calling [`Self::update`] for each voxel, and reuses
+    /// the current allocations where possible.
+    pub fn set_identifiers(&mut self, identifiers: &[TileIdentifier], registry: &TileRegistry) {
+        assert_eq!(identifiers.len(), Self::LENGTH, "Expected one identifier per voxel");
+        let first = identifiers[0];
+        if identifiers.iter().all(|&id| id == first) {
+            self.identifiers = ChunkIdentifiers::Uniform(first);
+        } else {
+            match (&mut self.identifiers, self.mode) {
+                (ChunkIdentifiers::Direct(current), ChunkIdentifierMode::Direct) => current.copy_from_slice(identifiers),
+                (_, ChunkIdentifierMode::Direct) => {
+                    let mut current = Self::alloc_direct(first);
+                    current.copy_from_slice(identifiers);
+                    self.identifiers = ChunkIdentifiers::Direct(current);
+                },
+                (_, ChunkIdentifierMode::Palette) => self.identifiers = ChunkIdentifiers::Palette(IdentifierPalette::from_identifiers(identifiers)),
+            }
         }
+        self.rebuild_visibility(registry);
+    }
+
+    /// Rebuilds the visibility planes of every voxel from the registry, for
+    /// example after tile opacities changed. Marks the whole chunk as dirty.
+    pub fn rebuild_visibility(&mut self, registry: &TileRegistry) {
+        // Empty voxels are collected in an extra layer, keeping the loop branchless
+        const LAYER_BY_OPACITY: [usize; 4] = [VIS_LAYER_COUNT, VIS_LAYER_OPAQUE, VIS_LAYER_CUTOUT, VIS_LAYER_TRANSLUCENT];
+        self.dirty = DirtySubChunks::ALL;
+        let get_layer = |opacity: TileOpacity| LAYER_BY_OPACITY[opacity as usize];
+        let get_voxel = |id: TileIdentifier| (get_layer(registry.get_cube_opacity(id)), registry.get_shape_occlusion(id));
+        let rows = match &self.identifiers {
+            ChunkIdentifiers::Uniform(id) => {
+                let opacity = registry.get_cube_opacity(*id);
+                for (i, vis_data) in self.vis_data.iter_mut().enumerate() {
+                    *vis_data = ChunkVisibility::Uniform(Self::get_vis_layer(opacity) == Some(i));
+                }
+                self.occluders = Self::uniform_occluders(registry.get_shape_occlusion(*id));
+                return;
+            },
+            ChunkIdentifiers::Direct(identifiers) => {
+                Self::build_vis_rows(|idx| get_voxel(identifiers[idx]))
+            },
+            ChunkIdentifiers::Palette(identifiers) => {
+                let voxels: Vec<(usize, u8)> = identifiers.palette().iter().map(|&id| get_voxel(id)).collect();
+                Self::build_vis_rows(|idx| voxels[identifiers.get_index(idx)])
+            },
+        };
+
+        for (vis_data, rows) in self.vis_data.iter_mut().chain(self.occluders.iter_mut()).zip(rows.iter()) {
+            vis_data.rebuild(rows);
+        }
+    }
+
+    /// Packs the visibility layer and shape occlusion of each voxel into rows
+    /// along x, indexed by `y + z*SIZE`. The rows of each visibility layer
+    /// are followed by the rows of each occluded face.
+    fn build_vis_rows(get_voxel: impl Fn(usize) -> (usize, u8)) -> Vec<Vec<u64>> {
+        let mut rows = vec![vec![0; SIZE*SIZE]; VIS_LAYER_COUNT + 6];
+        for row in 0..SIZE*SIZE {
+            let mut masks     = [0_u64; VIS_LAYER_COUNT + 1];
+            let mut occluders = [0_u64; 6];
+            for x in 0..SIZE {
+                let (layer, occlusion) = get_voxel(row*SIZE + x);
+                masks[layer] |= 1 << x;
+                for (face, mask) in occluders.iter_mut().enumerate() {
+                    *mask |= u64::from((occlusion >> face) & 1) << x;
+                }
+            }
+            for (rows, mask) in rows.iter_mut().zip(masks.into_iter().take(VIS_LAYER_COUNT).chain(occluders)) {
+                rows[row] = mask;
+            }
+        }
+        rows
+    }
+
+    /// Sets the tile at `pos`, marking the sub-chunks it affects as dirty if
+    /// it changed.
+    pub fn update(&mut self, pos: PosBlock<SIZE>, id: TileIdentifier, registry: &TileRegistry) {
+        if self.get(pos) == id {
+            return;
+        }
+
+        self.update_identifier(pos, id);
+        self.update_visibility(pos, registry.get_cube_opacity(id), registry.get_shape_occlusion(id));
+
+        let range = 0..(SIZE as i16);
+        DirtySubChunks::for_each_affected_offset(pos, |[x, y, z]| {
+            let pos = PosBlock::new(pos.x + x, pos.y + y, pos.z + z);
+            if range.contains(&pos.x) && range.contains(&pos.y) && range.contains(&pos.z) {
+                self.dirty.insert(DirtySubChunks::<SIZE>::sub_chunk_of(pos));
+            }
+        });
+    }
+
+    #[must_use]
+    pub fn get(&self, pos: PosBlock<SIZE>) -> TileIdentifier {
+        match &self.identifiers {
+            ChunkIdentifiers::Uniform(id)          => *id,
+            ChunkIdentifiers::Direct(identifiers)  => identifiers[pos.to_idx()],
+            ChunkIdentifiers::Palette(identifiers) => identifiers.get(pos.to_idx()),
+        }
+    }
+
+    /// Returns the value index of a state property of the tile at `pos`,
+    /// see [`TileRegistry::get_property`].
+    #[must_use]
+    pub fn get_property(&self, pos: PosBlock<SIZE>, name: &str, registry: &TileRegistry) -> Option<u8> {
+        registry.get_property(self.get(pos), name)
+    }
+
+    /// Sets a state property of the tile at `pos`. Returns false, leaving the
+    /// tile unchanged, if it doesn't have the property or the value is out of
+    /// range.
+    pub fn set_property(&mut self, pos: PosBlock<SIZE>, name: &str, value: u8, registry: &TileRegistry) -> bool {
+        let Some(id) = registry.with_property(self.get(pos), name, value) else { return false; };
+        self.update(pos, id, registry);
+        true
+    }
+
+    #[must_use]
+    pub fn get_property_value<T: TileStateValue>(&self, pos: PosBlock<SIZE>, name: &str, registry: &TileRegistry) -> Option<T> {
+        registry.get_property_value(self.get(pos), name)
     }
 
+    pub fn set_property_value<T: TileStateValue>(&mut self, pos: PosBlock<SIZE>, name: &str, value: T, registry: &TileRegistry) -> bool {
+        self.set_property(pos, name, value.to_index(), registry)
+    }
+
+    /// Returns if the voxel at `pos` holds an opaque tile, read from the
+    /// visibility planes rather than the registry.
     #[must_use]
-    pub const fn get(&self, pos: PosBlock) -> TileIdentifier {
-        self.identifiers[pos.to_idx()]
+    pub fn is_opaque(&self, pos: PosBlock<SIZE>) -> bool {
+        let [idx, vis] = Self::get_vis_idx(pos, VisAxis::X);
+        self.vis_data[VIS_LAYER_OPAQUE].get(vis).get(idx as u32)
+    }
+
+    /// Sub-chunks changed since the last [`Self::drain_dirty`].
+    #[must_use]
+    pub const fn dirty(&self) -> DirtySubChunks<SIZE> {
+        self.dirty
+    }
+
+    /// Returns and clears the sub-chunks changed since the last call.
+    pub const fn drain_dirty(&mut self) -> DirtySubChunks<SIZE> {
+        core::mem::replace(&mut self.dirty, DirtySubChunks::NONE)
+    }
+
+    /// Flags a sub-chunk for remeshing, such as when a neighbouring chunk
+    /// changed next to it.
+    pub const fn mark_dirty(&mut self, sub_chunk: [usize; 3]) {
+        self.dirty.insert(sub_chunk);
+    }
+
+    pub const fn mark_all_dirty(&mut self) {
+        self.dirty = DirtySubChunks::ALL;
+    }
+
+    /// Returns the identifier shared by every voxel, if the chunk is stored
+    /// uniformly.
+    #[must_use]
+    pub const fn get_uniform(&self) -> Option<TileIdentifier> {
+        match self.identifiers {
+            ChunkIdentifiers::Uniform(id) => Some(id),
+            _ => None,
+        }
     }
 
 }
 
-impl ChunkStorage {
+impl<const SIZE: usize> ChunkStorage<SIZE> {
+
+    fn update_identifier(&mut self, pos: PosBlock<SIZE>, id: TileIdentifier) {
+        if let ChunkIdentifiers::Uniform(current) = self.identifiers {
+            if current == id {
+                return;
+            }
+            self.identifiers = match self.mode {
+                ChunkIdentifierMode::Direct  => ChunkIdentifiers::Direct(Self::alloc_direct(current)),
+                ChunkIdentifierMode::Palette => ChunkIdentifiers::Palette(IdentifierPalette::new_filled(current)),
+            };
+        }
+
+        match &mut self.identifiers {
+            ChunkIdentifiers::Uniform(_) => unreachable!(),
+            ChunkIdentifiers::Direct(identifiers)  => identifiers[pos.to_idx()] = id,
+            ChunkIdentifiers::Palette(identifiers) => identifiers.set(pos.to_idx(), id),
+        }
+    }
 
-    const fn get_vis_idx(pos: PosBlock, axis: VisAxis) -> [usize; 2] {
+    fn update_visibility(&mut self, pos: PosBlock<SIZE>, opacity: TileOpacity, occlusion: u8) {
+        let layer = Self::get_vis_layer(opacity);
+        for (i, vis_data) in self.vis_data.iter_mut().enumerate() {
+            vis_data.set(pos, layer == Some(i));
+        }
+        for (face, occluders) in self.occluders.iter_mut().enumerate() {
+            occluders.set(pos, occlusion & (1 << face) != 0);
+        }
+    }
+
+    const fn uniform_occluders(occlusion: u8) -> [ChunkVisibility<SIZE>; 6] {
+        [
+            ChunkVisibility::Uniform(occlusion & (1 << VisFace::PosX as u8) != 0),
+            ChunkVisibility::Uniform(occlusion & (1 << VisFace::PosY as u8) != 0),
+            ChunkVisibility::Uniform(occlusion & (1 << VisFace::PosZ as u8) != 0),
+            ChunkVisibility::Uniform(occlusion & (1 << VisFace::NegX as u8) != 0),
+            ChunkVisibility::Uniform(occlusion & (1 << VisFace::NegY as u8) != 0),
+            ChunkVisibility::Uniform(occlusion & (1 << VisFace::NegZ as u8) != 0),
+        ]
+    }
+
+    const fn get_vis_layer(opacity: TileOpacity) -> Option<usize> {
+        match opacity {
+            TileOpacity::Empty       => None,
+            TileOpacity::Opaque      => Some(VIS_LAYER_OPAQUE),
+            TileOpacity::Cutout      => Some(VIS_LAYER_CUTOUT),
+            TileOpacity::Translucent => Some(VIS_LAYER_TRANSLUCENT),
+        }
+    }
+
+}
+
+impl<const SIZE: usize> ChunkStorage<SIZE> {
+
+    /// The storage used once the chunk stops being uniform.
+    #[must_use]
+    pub const fn identifier_mode(&self) -> ChunkIdentifierMode {
+        self.mode
+    }
+
+    pub fn set_identifier_mode(&mut self, mode: ChunkIdentifierMode) {
+        self.mode = mode;
+        self.identifiers = match (&self.identifiers, mode) {
+            (ChunkIdentifiers::Direct(identifiers), ChunkIdentifierMode::Palette) => {
+                ChunkIdentifiers::Palette(IdentifierPalette::from_identifiers(identifiers))
+            },
+            (ChunkIdentifiers::Palette(identifiers), ChunkIdentifierMode::Direct) => {
+                let mut result = Self::alloc_direct(TileIdentifier::DEFAULT);
+                for (idx, id) in result.iter_mut().enumerate() {
+                    *id = identifiers.get(idx);
+                }
+                ChunkIdentifiers::Direct(result)
+            },
+            _ => return,
+        };
+    }
+
+    /// Returns the identifiers of this chunk in palette form, converting from
+    /// the current storage if required.
+    #[must_use]
+    pub fn to_identifier_palette(&self) -> Cow<'_, IdentifierPalette<SIZE>> {
+        match &self.identifiers {
+            ChunkIdentifiers::Uniform(id)          => Cow::Owned(IdentifierPalette::new_filled(*id)),
+            ChunkIdentifiers::Direct(identifiers)  => Cow::Owned(IdentifierPalette::from_identifiers(identifiers)),
+            ChunkIdentifiers::Palette(identifiers) => Cow::Borrowed(identifiers),
+        }
+    }
+
+    /// Releases palette entries that are no longer in use, reducing the bits
+    /// stored per voxel where possible. Chunks left holding a single
+    /// identifier are collapsed back into uniform storage.
+    pub fn shrink_identifiers(&mut self) {
+        let uniform = match &mut self.identifiers {
+            ChunkIdentifiers::Uniform(_) => return,
+            ChunkIdentifiers::Direct(identifiers) => {
+                let first = identifiers[0];
+                identifiers.iter().all(|&id| id == first).then_some(first)
+            },
+            ChunkIdentifiers::Palette(identifiers) => {
+                identifiers.shrink();
+                (identifiers.palette().len() == 1).then(|| identifiers.palette()[0])
+            },
+        };
+
+        if let Some(id) = uniform {
+            self.identifiers = ChunkIdentifiers::Uniform(id);
+        }
+    }
+
+    /// Collapses each layer of visibility planes back into uniform storage if
+    /// every voxel is either in or out of that layer.
+    pub fn shrink_visibility(&mut self) {
+        for vis_data in self.vis_data.iter_mut().chain(self.occluders.iter_mut()) {
+            vis_data.shrink();
+        }
+    }
+
+    pub fn shrink(&mut self) {
+        self.shrink_identifiers();
+        self.shrink_visibility();
+    }
+
+    /// Approximate heap usage of the identifier storage in bytes.
+    #[must_use]
+    pub fn identifier_heap_size(&self) -> usize {
+        match &self.identifiers {
+            ChunkIdentifiers::Uniform(_)           => 0,
+            ChunkIdentifiers::Direct(_)            => Self::LENGTH*size_of::<TileIdentifier>(),
+            ChunkIdentifiers::Palette(identifiers) => identifiers.heap_size(),
+        }
+    }
+
+    /// Approximate heap usage of the visibility planes in bytes.
+    #[must_use]
+    pub const fn visibility_heap_size(&self) -> usize {
+        let mut result =
+            self.vis_data[VIS_LAYER_OPAQUE].heap_size() +
+            self.vis_data[VIS_LAYER_CUTOUT].heap_size() +
+            self.vis_data[VIS_LAYER_TRANSLUCENT].heap_size();
+        let mut face = 0;
+        while face < self.occluders.len() {
+            result += self.occluders[face].heap_size();
+            face += 1;
+        }
+        result
+    }
+
+    fn alloc_direct(id: TileIdentifier) -> Box<[TileIdentifier]> {
+        vec![id; Self::LENGTH].into_boxed_slice()
+    }
+
+}
+
+impl<const SIZE: usize> ChunkStorage<SIZE> {
+
+    const fn get_vis_idx(pos: PosBlock<SIZE>, axis: VisAxis) -> [usize; 2] {
         let [blk_idx, layer, vis_idx] = Self::get_vis_idx_and_layer(pos, axis);
         [blk_idx, vis_idx + layer]
     }
 
-    const fn get_vis_idx_and_layer(pos: PosBlock, axis: VisAxis) -> [usize; 3] {
+    const fn get_vis_idx_and_layer(pos: PosBlock<SIZE>, axis: VisAxis) -> [usize; 3] {
         let [x, y, layer] = axis.to_local_usize([pos.x as usize, pos.y as usize, pos.z as usize]);
-        let start = (axis as usize) * CHUNK_VIS_SIZE*CHUNK_VIS_SIZE*CHUNK_SIZE;
-        let off_xy = (x >> 3)*CHUNK_SIZE + (y >> 3)*CHUNK_VIS_SIZE*CHUNK_SIZE;
+        let start = (axis as usize) * Self::VIS_SIZE*Self::VIS_SIZE*SIZE;
+        let off_xy = (x >> 3)*SIZE + (y >> 3)*Self::VIS_SIZE*SIZE;
         [
             (x & 0x07) | ((y & 0x07) << 3), 
             layer, 
@@ -67,24 +633,292 @@ impl ChunkStorage {
 
 }
 
-impl FaceVisibilityProvider for ChunkStorage {
+impl<const SIZE: usize> ChunkStorage<SIZE> {
+
+    /// Returns which voxels have the given opacity in one layer of the given
+    /// 8x8 column, indexed along `axis` like the face visibility planes.
+    #[must_use]
+    pub const fn get_solid_plane(&self, chunk: [usize; 3], axis: VisAxis, layer: usize, opacity: TileOpacity) -> BitPlane {
+        let Some(vis_layer) = Self::get_vis_layer(opacity) else { return BitPlane::DEFAULT; };
+        let pos = PosBlock::new((chunk[0]*8) as i16, (chunk[1]*8) as i16, (chunk[2]*8) as i16);
+        let [_, _, base_vis] = Self::get_vis_idx_and_layer(pos, axis);
+        self.vis_data[vis_layer].get(base_vis + layer)
+    }
+
+    /// Returns the plane of this chunk that culls faces of tiles with the
+    /// given opacity on the border of the neighbouring chunk `culled` for
+    /// `face`, see [`Self::get_face_visibility_plane_with_border`].
+    #[must_use]
+    pub fn get_border_plane(&self, culled: &Self, chunk: [usize; 3], face: VisFace, opacity: TileOpacity) -> BitPlane {
+        let (layer, culled_layer) = if face < VisFace::NegX { (SIZE-1, 0) } else { (0, SIZE-1) };
+        self.get_cull_plane(culled, chunk, face, layer, culled_layer, opacity)
+    }
+
+    /// Returns the visible faces of tiles with the given opacity, culling
+    /// faces on the chunk border against `border` instead of treating the
+    /// neighbour as empty. `border` is the neighbour's [`Self::get_border_plane`].
+    #[must_use]
+    pub fn get_face_visibility_plane_with_border(
+        &self,
+        chunk:   [usize; 3],
+        face:    VisFace,
+        layer:   u32,
+        opacity: TileOpacity,
+        border:  BitPlane,
+    ) -> BitPlane {
+        let Some(vis_layer) = Self::get_vis_layer(opacity) else { return BitPlane::DEFAULT; };
+        let vis_data = &self.vis_data[vis_layer];
+        if matches!(vis_data, ChunkVisibility::Uniform(false)) {
+            return BitPlane::DEFAULT;
+        }
 
-    fn get_face_visibility_plane(&self, chunk: [usize; 3], face: VisFace, layer: u32) -> BitPlane {
         let pos = PosBlock::new((chunk[0]*8) as i16, (chunk[1]*8) as i16, (chunk[2]*8) as i16);
 
         let [_, base_layer, base_vis] = Self::get_vis_idx_and_layer(pos, face.axis());
 
         let layer = base_layer + layer as usize;
-        let base_vis_plane = self.vis_data[base_vis + layer];
+
+        let base_vis_plane = vis_data.get(base_vis + layer);
 
         #[allow(clippy::match_bool)]
         let cull_vis_plane = match face < VisFace::NegX {
-            true  if layer > 0            => self.vis_data[base_vis + layer - 1],
-            false if layer < CHUNK_SIZE-1 => self.vis_data[base_vis + layer + 1],
-            _ => BitPlane::DEFAULT
+            true  if layer > 0            => self.get_cull_plane(self, chunk, face, layer - 1, layer, opacity),
+            false if layer < SIZE-1       => self.get_cull_plane(self, chunk, face, layer + 1, layer, opacity),
+            _ => border
         };
 
         base_vis_plane & !cull_vis_plane
     }
 
+    /// Returns the faces of tiles with the given opacity in `culled_layer` of
+    /// `culled` hidden by `layer` of this chunk, both absolute layers of the
+    /// 8x8 column along `face`.
+    ///
+    /// Opaque tiles hide every face, translucent tiles also hide the faces of
+    /// neighbouring voxels of the same translucent tile, so glass next to
+    /// water keeps both surfaces. Cutout tiles never hide faces. Opaque
+    /// shapes hide `face` of the voxel they cover the opposite side of.
+    fn get_cull_plane(&self, culled: &Self, chunk: [usize; 3], face: VisFace, layer: usize, culled_layer: usize, opacity: TileOpacity) -> BitPlane {
+        let axis = face.axis();
+        let pos  = PosBlock::new((chunk[0]*8) as i16, (chunk[1]*8) as i16, (chunk[2]*8) as i16);
+        let [_, _, base_vis] = Self::get_vis_idx_and_layer(pos, axis);
+        let idx = base_vis + layer;
+
+        let opaque = self.vis_data[VIS_LAYER_OPAQUE].get(idx) | self.occluders[face.reverse() as usize].get(idx);
+        if !matches!(opacity, TileOpacity::Translucent) {
+            return opaque;
+        }
+
+        // Only voxels translucent on both sides need their tiles compared
+        let mut candidates = (self.vis_data[VIS_LAYER_TRANSLUCENT].get(idx) & culled.vis_data[VIS_LAYER_TRANSLUCENT].get(base_vis + culled_layer)).to_raw();
+        let base = axis.to_local_usize(chunk).map(|v| v*8);
+        let pos_at = |i: u32, layer: usize| {
+            let [x, y] = BitPlane::pos_from_idx(i);
+            let [x, y, z] = axis.to_world_usize([base[0] + x as usize, base[1] + y as usize, layer]);
+            PosBlock::new(x as i16, y as i16, z as i16)
+        };
+
+        let mut same = BitPlane::DEFAULT;
+        while candidates != 0 {
+            let i = candidates.trailing_zeros();
+            candidates &= candidates - 1;
+            if self.get(pos_at(i, layer)) == culled.get(pos_at(i, culled_layer)) {
+                same.mark(i);
+            }
+        }
+        opaque | same
+    }
+
+}
+
+impl<const SIZE: usize> FaceVisibilityProvider for ChunkStorage<SIZE> {
+
+    const CHUNK_SIZE: usize = SIZE;
+
+    fn get_face_visibility_plane(&self, chunk: [usize; 3], face: VisFace, layer: u32) -> BitPlane {
+        self.get_face_visibility_plane_with_border(chunk, face, layer, TileOpacity::Opaque, BitPlane::DEFAULT)
+    }
+
+    fn get_face_visibility_plane_for(&self, chunk: [usize; 3], face: VisFace, layer: u32, opacity: TileOpacity) -> BitPlane {
+        self.get_face_visibility_plane_with_border(chunk, face, layer, opacity, BitPlane::DEFAULT)
+    }
+
+}
+
+impl<const SIZE: usize> FaceTileProvider for ChunkStorage<SIZE> {
+
+    fn get_face_tile(&self, pos: [usize; 3]) -> TileIdentifier {
+        self.get(PosBlock::new(pos[0] as i16, pos[1] as i16, pos[2] as i16))
+    }
+
+}
+
+impl<const SIZE: usize> FaceOcclusionProvider for ChunkStorage<SIZE> {
+
+    fn is_face_occluder(&self, pos: [isize; 3]) -> bool {
+        let range = 0..(SIZE as isize);
+        pos.iter().all(|v| range.contains(v)) && self.is_opaque(PosBlock::new(pos[0] as i16, pos[1] as i16, pos[2] as i16))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{meshing::{ChunkMesh, FaceVisibilityLayer}, tiles::TileDefinition, world::{ChunkNeighbourhood, UnloadedChunkMode}};
+
+    use super::*;
+
+    fn registry() -> (TileRegistry, [TileIdentifier; 4]) {
+        let mut registry = TileRegistry::new();
+        let ids = [TileOpacity::Opaque, TileOpacity::Cutout, TileOpacity::Translucent, TileOpacity::Empty].map(|opacity| {
+            let name = format!("test:{opacity:?}").to_lowercase();
+            let mut definition = TileDefinition::new_opaque(name.as_str());
+            definition.opacity = opacity;
+            registry.register(&name, definition).unwrap()
+        });
+        (registry, ids)
+    }
+
+    fn identifiers<const SIZE: usize>(ids: [TileIdentifier; 4]) -> Vec<TileIdentifier> {
+        (0..ChunkDimensions::<SIZE>::LENGTH).map(|idx| {
+            let pos = PosBlock::<SIZE>::from_idx(idx);
+            match (pos.x*5 + pos.y*3 + pos.z*7) % 9 {
+                0..=2 => ids[0],
+                3     => ids[1],
+                4     => ids[2],
+                5     => ids[3],
+                _     => TileIdentifier::DEFAULT,
+            }
+        }).collect()
+    }
+
+    fn assert_same_visibility<const SIZE: usize>(a: &ChunkStorage<SIZE>, b: &ChunkStorage<SIZE>) {
+        let vis_size = ChunkDimensions::<SIZE>::VIS_SIZE;
+        for idx in 0..ChunkDimensions::<SIZE>::LENGTH {
+            let pos = PosBlock::from_idx(idx);
+            assert_eq!(a.get(pos), b.get(pos));
+        }
+        for chunk in (0..vis_size*vis_size*vis_size).map(|i| [i % vis_size, (i / vis_size) % vis_size, i / (vis_size*vis_size)]) {
+            for face in VisFace::ALL {
+                for layer in 0..8 {
+                    for opacity in [TileOpacity::Opaque, TileOpacity::Cutout, TileOpacity::Translucent] {
+                        assert_eq!(
+                            a.get_face_visibility_plane_for(chunk, face, layer, opacity),
+                            b.get_face_visibility_plane_for(chunk, face, layer, opacity),
+                            "{chunk:?} {face:?} {layer} {opacity:?}",
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_update_matches_bulk<const SIZE: usize>() {
+        let (registry, ids) = registry();
+        let identifiers = identifiers::<SIZE>(ids);
+        for mode in [ChunkIdentifierMode::Direct, ChunkIdentifierMode::Palette] {
+            let mut updated = ChunkStorage::<SIZE>::new_empty_with_mode(mode);
+            for (idx, &id) in identifiers.iter().enumerate() {
+                updated.update(PosBlock::from_idx(idx), id, &registry);
+            }
+            let bulk = ChunkStorage::<SIZE>::from_identifiers(&identifiers, mode, &registry);
+            assert_same_visibility(&updated, &bulk);
+
+            let mut rebuilt = ChunkStorage::<SIZE>::from_identifiers(&identifiers, mode, &registry);
+            rebuilt.set_identifier_mode(match mode {
+                ChunkIdentifierMode::Direct  => ChunkIdentifierMode::Palette,
+                ChunkIdentifierMode::Palette => ChunkIdentifierMode::Direct,
+            });
+            rebuilt.rebuild_visibility(&registry);
+            assert_same_visibility(&updated, &rebuilt);
+        }
+    }
+
+    #[test]
+    fn transpose_matches_naive() {
+        for x in [0, u64::MAX, 1, 0x8000_0000_0000_0000, 0x0123_4567_89AB_CDEF, 0xF0E1_D2C3_B4A5_9687] {
+            let mut expected = 0;
+            for row in 0..8 {
+                for col in 0..8 {
+                    expected |= ((x >> (row*8 + col)) & 1) << (col*8 + row);
+                }
+            }
+            assert_eq!(transpose_bits_8x8(x), expected, "{x:#x}");
+            assert_eq!(transpose_bits_8x8(expected), x);
+        }
+    }
+
+    #[test]
+    fn update_matches_set_identifiers() {
+        check_update_matches_bulk::<8>();
+        check_update_matches_bulk::<16>();
+        check_update_matches_bulk::<32>();
+        check_update_matches_bulk::<64>();
+    }
+
+    fn translucent_faces(vis: &impl FaceVisibilityProvider) -> usize {
+        let mut mesh = ChunkMesh::default();
+        mesh.build(&FaceVisibilityLayer::new(vis, TileOpacity::Translucent));
+        mesh.len()
+    }
+
+    #[test]
+    fn translucent_culls_same_tile() {
+        let mut registry = TileRegistry::new();
+        let [glass, water] = ["test:glass", "test:water"].map(|name| {
+            let mut definition = TileDefinition::new_opaque(name);
+            definition.opacity = TileOpacity::Translucent;
+            registry.register(name, definition).unwrap()
+        });
+        // Glass meets glass along x, then water
+        let mut storage = <ChunkStorage<16>>::new_empty();
+        storage.update(PosBlock::new(4, 4, 4), glass, &registry);
+        storage.update(PosBlock::new(5, 4, 4), glass, &registry);
+        storage.update(PosBlock::new(6, 4, 4), water, &registry);
+        assert_eq!(translucent_faces(&storage), 16);
+
+        // The same across a chunk border
+        let mut chunk = <ChunkStorage<16>>::new_empty();
+        chunk.update(PosBlock::new(15, 3, 4), glass, &registry);
+        for (tile, faces) in [(glass, 5), (water, 6)] {
+            let neighbour = <ChunkStorage<16>>::new_uniform(tile, &registry);
+            let mut neighbours = [None; 6];
+            neighbours[VisFace::PosX as usize] = Some(&neighbour);
+            let neighbourhood = ChunkNeighbourhood::new(&chunk, neighbours, UnloadedChunkMode::Empty);
+            assert_eq!(translucent_faces(&neighbourhood), faces, "{tile:?}");
+        }
+    }
+
+    #[test]
+    fn uniform_round_trip() {
+        let (registry, ids) = registry();
+        let mut storage = ChunkStorage::<16>::new_uniform(ids[0], &registry);
+        assert_eq!(storage.identifier_heap_size() + storage.visibility_heap_size(), 0);
+        storage.update(PosBlock::new(3, 4, 5), ids[1], &registry);
+        assert_eq!(storage.get_uniform(), None);
+        assert_ne!(storage.identifier_heap_size(), 0);
+        storage.update(PosBlock::new(3, 4, 5), ids[0], &registry);
+        storage.shrink();
+        assert_eq!(storage.get_uniform(), Some(ids[0]));
+        assert_eq!(storage.identifier_heap_size() + storage.visibility_heap_size(), 0);
+        assert_same_visibility(&storage, &ChunkStorage::<16>::new_uniform(ids[0], &registry));
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let (registry, ids) = registry();
+        let mut storage = <ChunkStorage>::new_empty();
+        assert_eq!(storage.drain_dirty(), DirtySubChunks::ALL);
+
+        storage.update(PosBlock::new(8, 0, 31), ids[0], &registry);
+        assert_eq!(storage.dirty().iter().collect::<Vec<_>>(), [[0, 0, 3], [1, 0, 3]]);
+        storage.drain_dirty();
+
+        storage.update(PosBlock::new(8, 0, 31), ids[0], &registry);
+        assert!(storage.dirty().is_empty());
+
+        storage.rebuild_visibility(&registry);
+        assert_eq!(storage.drain_dirty(), DirtySubChunks::ALL);
+    }
+
 }
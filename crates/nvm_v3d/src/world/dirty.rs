@@ -0,0 +1,176 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use super::{ChunkDimensions, PosBlock, CHUNK_SIZE};
+
+/// Words of [`DirtySubChunks`], enough for the 512 sub-chunks of the largest
+/// chunk size.
+const DIRTY_WORDS: usize = 8;
+
+/// Set of the 8x8x8 sub-chunks of a chunk, the units meshed by
+/// [`crate::meshing::mesh_chunk_plane`], that need remeshing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DirtySubChunks<const SIZE: usize = CHUNK_SIZE>([u64; DIRTY_WORDS]);
+
+impl<const SIZE: usize> DirtySubChunks<SIZE> {
+
+    const VIS_SIZE:   usize = ChunkDimensions::<SIZE>::VIS_SIZE;
+    const VIS_LENGTH: usize = ChunkDimensions::<SIZE>::VIS_LENGTH;
+
+    pub const NONE: Self = Self([0; DIRTY_WORDS]);
+    pub const ALL:  Self = {
+        let mut words = [0; DIRTY_WORDS];
+        let mut i = 0;
+        while i < Self::VIS_LENGTH {
+            words[i / 64] |= 1 << (i % 64);
+            i += 1;
+        }
+        Self(words)
+    };
+
+    /// Creates a set from one bit per sub-chunk, see [`Self::to_raw`]. Bits
+    /// past the sub-chunks of the chunk are ignored.
+    #[must_use]
+    pub const fn from_raw(raw: [u64; DIRTY_WORDS]) -> Self {
+        let mut words = raw;
+        let mut i = 0;
+        while i < DIRTY_WORDS {
+            words[i] &= Self::ALL.0[i];
+            i += 1;
+        }
+        Self(words)
+    }
+
+    /// One bit per sub-chunk, indexed by `x + y*n + z*n*n` with `n` sub-chunks
+    /// along each axis.
+    #[must_use]
+    pub const fn to_raw(self) -> [u64; DIRTY_WORDS] {
+        self.0
+    }
+
+    /// Returns the sub-chunk holding the block.
+    #[must_use]
+    pub const fn sub_chunk_of(pos: PosBlock<SIZE>) -> [usize; 3] {
+        [(pos.x as usize) >> 3, (pos.y as usize) >> 3, (pos.z as usize) >> 3]
+    }
+
+    pub const fn insert(&mut self, sub_chunk: [usize; 3]) {
+        let idx = Self::idx(sub_chunk);
+        self.0[idx / 64] |= 1 << (idx % 64);
+    }
+
+    #[must_use]
+    pub const fn contains(self, sub_chunk: [usize; 3]) -> bool {
+        let idx = Self::idx(sub_chunk);
+        (self.0[idx / 64] & (1 << (idx % 64))) != 0
+    }
+
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        let mut i = 0;
+        while i < DIRTY_WORDS {
+            if self.0[i] != 0 {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    #[must_use]
+    pub const fn len(self) -> usize {
+        let mut result = 0;
+        let mut i = 0;
+        while i < DIRTY_WORDS {
+            result += self.0[i].count_ones() as usize;
+            i += 1;
+        }
+        result
+    }
+
+    /// Iterates the sub-chunks in the set.
+    pub fn iter(self) -> impl Iterator<Item = [usize; 3]> {
+        let mut words = self.0;
+        let mut word  = 0;
+        core::iter::from_fn(move || {
+            while words.get(word)? == &0 {
+                word += 1;
+            }
+            let idx = word*64 + words[word].trailing_zeros() as usize;
+            words[word] &= words[word] - 1;
+            Some([
+                idx % Self::VIS_SIZE,
+                (idx / Self::VIS_SIZE) % Self::VIS_SIZE,
+                idx / (Self::VIS_SIZE*Self::VIS_SIZE),
+            ])
+        })
+    }
+
+    const fn idx(sub_chunk: [usize; 3]) -> usize {
+        sub_chunk[0] + sub_chunk[1]*Self::VIS_SIZE + sub_chunk[2]*Self::VIS_SIZE*Self::VIS_SIZE
+    }
+
+    /// Calls `f` with the offset of every voxel whose sub-chunk is affected by
+    /// an edit at `pos`. Faces and ambient occlusion read the neighbouring
+    /// voxels, so edits on a sub-chunk border also reach across it, including
+    /// diagonally.
+    pub(crate) fn for_each_affected_offset(pos: PosBlock<SIZE>, mut f: impl FnMut([i16; 3])) {
+        let range = |v: i16| {
+            let v = v & 0x07;
+            -i16::from(v == 0)..=i16::from(v == 7)
+        };
+        for z in range(pos.z) {
+            for y in range(pos.y) {
+                for x in range(pos.x) {
+                    f([x, y, z]);
+                }
+            }
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn affected(pos: PosBlock) -> Vec<[i16; 3]> {
+        let mut result = Vec::new();
+        DirtySubChunks::for_each_affected_offset(pos, |offset| result.push(offset));
+        result
+    }
+
+    #[test]
+    fn affected_offsets_reach_across_borders() {
+        assert_eq!(affected(PosBlock::new(3, 4, 5)), [[0, 0, 0]]);
+        assert_eq!(affected(PosBlock::new(8, 4, 5)), [[-1, 0, 0], [0, 0, 0]]);
+        assert_eq!(affected(PosBlock::new(3, 15, 5)), [[0, 0, 0], [0, 1, 0]]);
+
+        let corner = affected(PosBlock::new(0, 7, 24));
+        assert_eq!(corner.len(), 8);
+        assert!(corner.contains(&[-1, 1, -1]));
+        assert!(corner.iter().all(|&[x, y, z]| (-1..=0).contains(&x) && (0..=1).contains(&y) && (-1..=0).contains(&z)));
+    }
+
+    fn check_all<const SIZE: usize>() {
+        let sub_chunks = SIZE/8;
+        let all = DirtySubChunks::<SIZE>::ALL;
+        assert_eq!(all.len(), sub_chunks*sub_chunks*sub_chunks);
+        assert_eq!(DirtySubChunks::<SIZE>::from_raw([u64::MAX; DIRTY_WORDS]), all);
+        assert_eq!(all.iter().count(), all.len());
+        assert!(all.iter().all(|sub_chunk| all.contains(sub_chunk) && sub_chunk.iter().all(|&v| v < sub_chunks)));
+
+        let mut dirty = DirtySubChunks::<SIZE>::NONE;
+        let last = PosBlock::<SIZE>::new(SIZE as i16 - 1, 0, SIZE as i16 - 1);
+        dirty.insert(DirtySubChunks::sub_chunk_of(last));
+        assert_eq!(dirty.iter().collect::<Vec<_>>(), [[sub_chunks - 1, 0, sub_chunks - 1]]);
+    }
+
+    #[test]
+    fn all_matches_chunk_size() {
+        check_all::<8>();
+        check_all::<16>();
+        check_all::<32>();
+        check_all::<64>();
+    }
+
+}
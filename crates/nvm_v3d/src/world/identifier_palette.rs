@@ -0,0 +1,247 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use crate::tiles::TileIdentifier;
+
+use super::{ChunkDimensions, CHUNK_SIZE};
+
+/// Bit-packed chunk identifier storage. Each voxel stores an index into a
+/// palette of the distinct identifiers present in the chunk, packed at 1, 2,
+/// 4, 8 or 16 bits per voxel. Widths are powers of two so entries never
+/// straddle words.
+#[derive(Debug, Clone)]
+pub struct IdentifierPalette<const SIZE: usize = CHUNK_SIZE> {
+    palette: Vec<TileIdentifier>,
+    bits:    u32,
+    data:    Box<[u64]>,
+}
+
+impl<const SIZE: usize> IdentifierPalette<SIZE> {
+
+    const LENGTH: usize = ChunkDimensions::<SIZE>::LENGTH;
+
+    #[must_use]
+    pub fn new_filled(id: TileIdentifier) -> Self {
+        Self{
+            palette: vec![id],
+            bits:    1,
+            data:    Self::alloc_data(1),
+        }
+    }
+
+    /// Packs one identifier per voxel of the chunk.
+    #[must_use]
+    pub fn from_identifiers(identifiers: &[TileIdentifier]) -> Self {
+        assert_eq!(identifiers.len(), Self::LENGTH, "Expected one identifier per voxel");
+        let mut result = Self::new_filled(identifiers[0]);
+        for (idx, &id) in identifiers.iter().enumerate().skip(1) {
+            result.set(idx, id);
+        }
+        result
+    }
+
+    /// Reconstructs a palette from its raw parts, as produced by [`Self::palette`],
+    /// [`Self::bits_per_voxel`] and [`Self::words`]. Returns `None` if the
+    /// parts are inconsistent.
+    #[must_use]
+    pub fn from_raw_parts(palette: Vec<TileIdentifier>, bits: u32, words: Box<[u64]>) -> Option<Self> {
+        let is_valid =
+            matches!(bits, 1 | 2 | 4 | 8 | 16) &&
+            !palette.is_empty() &&
+            palette.len() <= (1 << bits) &&
+            words.len() == Self::LENGTH * (bits as usize) / 64;
+
+        if !is_valid {
+            return None;
+        }
+
+        let result = Self{ palette, bits, data: words };
+        (0..Self::LENGTH).all(|idx| result.get_index(idx) < result.palette.len()).then_some(result)
+    }
+
+    #[must_use]
+    pub fn get(&self, idx: usize) -> TileIdentifier {
+        self.palette[self.get_index(idx)]
+    }
+
+    pub fn set(&mut self, idx: usize, id: TileIdentifier) {
+        let palette_idx = self.get_or_insert_palette_index(id);
+        self.set_index(idx, palette_idx);
+    }
+
+    /// Drops palette entries no longer referenced by any voxel and repacks
+    /// the data at the smallest width that fits the remaining entries.
+    pub fn shrink(&mut self) {
+        let mut used = vec![false; self.palette.len()];
+        for idx in 0..Self::LENGTH {
+            used[self.get_index(idx)] = true;
+        }
+
+        let mut remap   = vec![0; self.palette.len()];
+        let mut palette = Vec::with_capacity(self.palette.len());
+        for (i, &id) in self.palette.iter().enumerate() {
+            if used[i] {
+                remap[i] = palette.len();
+                palette.push(id);
+            }
+        }
+
+        let mut result = Self{
+            bits:    Self::bits_for_len(palette.len()),
+            data:    Self::alloc_data(Self::bits_for_len(palette.len())),
+            palette,
+        };
+        for idx in 0..Self::LENGTH {
+            result.set_index(idx, remap[self.get_index(idx)]);
+        }
+        *self = result;
+    }
+
+    #[must_use]
+    pub fn palette(&self) -> &[TileIdentifier] {
+        &self.palette
+    }
+
+    #[must_use]
+    pub const fn bits_per_voxel(&self) -> u32 {
+        self.bits
+    }
+
+    #[must_use]
+    pub const fn words(&self) -> &[u64] {
+        &self.data
+    }
+
+    /// Approximate heap usage of this storage in bytes.
+    #[must_use]
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity()*size_of::<TileIdentifier>() + self.data.len()*size_of::<u64>()
+    }
+
+}
+
+impl<const SIZE: usize> IdentifierPalette<SIZE> {
+
+    #[must_use]
+    pub fn get_index(&self, idx: usize) -> usize {
+        let [word, shift] = self.locate(idx);
+        ((self.data[word] >> shift) & self.mask()) as usize
+    }
+
+    fn set_index(&mut self, idx: usize, palette_idx: usize) {
+        let [word, shift] = self.locate(idx);
+        let mask = self.mask();
+        self.data[word] = (self.data[word] & !(mask << shift)) | (((palette_idx as u64) & mask) << shift);
+    }
+
+    fn get_or_insert_palette_index(&mut self, id: TileIdentifier) -> usize {
+        if let Some(palette_idx) = self.palette.iter().position(|&v| v == id) {
+            return palette_idx;
+        }
+
+        self.palette.push(id);
+        let bits = Self::bits_for_len(self.palette.len());
+        if bits != self.bits {
+            self.repack(bits);
+        }
+        self.palette.len() - 1
+    }
+
+    fn repack(&mut self, bits: u32) {
+        let mut result = Self{
+            palette: Vec::new(),
+            bits,
+            data:    Self::alloc_data(bits),
+        };
+        for idx in 0..Self::LENGTH {
+            result.set_index(idx, self.get_index(idx));
+        }
+        self.bits = result.bits;
+        self.data = result.data;
+    }
+
+    const fn locate(&self, idx: usize) -> [usize; 2] {
+        let per_word = 64 / self.bits as usize;
+        [idx / per_word, (idx % per_word) * self.bits as usize]
+    }
+
+    const fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    const fn bits_for_len(len: usize) -> u32 {
+        match len {
+            0..=2   =>  1,
+            3..=4   =>  2,
+            5..=16  =>  4,
+            17..=256 => 8,
+            _       => 16,
+        }
+    }
+
+    fn alloc_data(bits: u32) -> Box<[u64]> {
+        vec![0; Self::LENGTH * (bits as usize) / 64].into_boxed_slice()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identifiers<const SIZE: usize>(count: u16) -> Vec<TileIdentifier> {
+        (0..ChunkDimensions::<SIZE>::LENGTH).map(|idx| TileIdentifier::from_raw((idx*7 % count as usize) as u16)).collect()
+    }
+
+    fn assert_contains<const SIZE: usize>(palette: &IdentifierPalette<SIZE>, identifiers: &[TileIdentifier]) {
+        for (idx, &id) in identifiers.iter().enumerate() {
+            assert_eq!(palette.get(idx), id, "{idx}");
+        }
+    }
+
+    #[test]
+    fn round_trip_each_width() {
+        for (count, bits) in [(1, 1), (2, 1), (3, 2), (16, 4), (17, 8), (256, 8), (300, 16)] {
+            let identifiers = identifiers::<8>(count);
+            let palette = IdentifierPalette::<8>::from_identifiers(&identifiers);
+            assert_eq!(palette.bits_per_voxel(), bits, "{count}");
+            assert_eq!(palette.palette().len(), count as usize);
+            assert_contains(&palette, &identifiers);
+
+            let raw = IdentifierPalette::<8>::from_raw_parts(palette.palette().to_vec(), bits, palette.words().into()).unwrap();
+            assert_contains(&raw, &identifiers);
+        }
+    }
+
+    #[test]
+    fn shrink_drops_unused_entries() {
+        let mut identifiers = identifiers::<32>(20);
+        let mut palette = IdentifierPalette::<32>::from_identifiers(&identifiers);
+        assert_eq!(palette.bits_per_voxel(), 8);
+
+        // Leave only the identifiers below 3 in use
+        for (idx, id) in identifiers.iter_mut().enumerate() {
+            if id.to_raw() >= 3 {
+                *id = TileIdentifier::from_raw(2);
+                palette.set(idx, *id);
+            }
+        }
+        assert_eq!(palette.palette().len(), 20);
+
+        palette.shrink();
+        assert_eq!(palette.palette().len(), 3);
+        assert_eq!(palette.bits_per_voxel(), 2);
+        assert_contains(&palette, &identifiers);
+    }
+
+    #[test]
+    fn raw_parts_rejects_inconsistent_data() {
+        let palette = IdentifierPalette::<8>::from_identifiers(&identifiers::<8>(3));
+        let words: Box<[u64]> = palette.words().into();
+        assert!(IdentifierPalette::<8>::from_raw_parts(palette.palette().to_vec(), 3, words.clone()).is_none());
+        assert!(IdentifierPalette::<8>::from_raw_parts(palette.palette().to_vec(), 4, words.clone()).is_none());
+        assert!(IdentifierPalette::<8>::from_raw_parts(Vec::new(), 2, words.clone()).is_none());
+        // Index 2 is stored but no longer in the palette
+        assert!(IdentifierPalette::<8>::from_raw_parts(palette.palette()[..2].to_vec(), 2, words).is_none());
+    }
+
+}
@@ -3,9 +3,20 @@
 mod pos;
 pub use pos::*;
 
+mod region;
+pub use region::*;
+
+mod identifier_palette;
+pub use identifier_palette::*;
+
 mod chunk_storage;
 pub use chunk_storage::*;
 
-// pub struct World {
-//     chunks: HashMap<PosChunk, ChunkStorage>,
-// }
\ No newline at end of file
+mod dirty;
+pub use dirty::*;
+
+mod world_storage;
+pub use world_storage::*;
+
+mod chunk_neighbourhood;
+pub use chunk_neighbourhood::*;
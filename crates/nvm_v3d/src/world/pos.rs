@@ -1,20 +1,123 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
+use core::{hash::Hash, ops::{Add, AddAssign, Sub, SubAssign}};
+
 use glam::IVec3;
 
-use super::{CHUNK_COORD_BITS, CHUNK_SIZE};
+use crate::meshing::VisFace;
+
+use super::{ChunkDimensions, CHUNK_SIZE};
 
 macro_rules! impl_world_pos {
-    ($name:ident) => {
+    ($(#[$meta:meta])* $name:ident $(<$size:ident>)?: $scalar:ty) => {
 
+        $(#[$meta])*
         #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
-        pub struct $name {
-            pub x: i16,
-            pub y: i16,
-            pub z: i16,
+        pub struct $name$(<const $size: usize = CHUNK_SIZE>)? {
+            pub x: $scalar,
+            pub y: $scalar,
+            pub z: $scalar,
         }
 
-        impl $name {
+        impl$(<const $size: usize>)? $name$(<$size>)? {
+
+            #[must_use]
+            pub const fn new(x: $scalar, y: $scalar, z: $scalar) -> Self {
+                Self{x, y, z}
+            }
+
+            #[must_use]
+            pub const fn as_ivec3(self) -> IVec3 {
+                IVec3::new(self.x as i32, self.y as i32, self.z as i32)
+            }
+
+            #[must_use]
+            pub const fn from_ivec3(other: IVec3) -> Self {
+                Self::new(other.x as $scalar, other.y as $scalar, other.z as $scalar)
+            }
+
+            /// The position sharing the given face with this one, offset by
+            /// [`VisFace::offset`].
+            #[must_use]
+            pub fn neighbour(self, face: VisFace) -> Self {
+                self + face.offset()
+            }
+
+            /// The six positions sharing a face with this one, keyed by the
+            /// face pointing towards them.
+            pub fn face_neighbours(self) -> impl Iterator<Item = (VisFace, Self)> {
+                VisFace::ALL.into_iter().map(move |face| (face, self + face.offset()))
+            }
+
+            /// The twelve positions sharing only an edge with this one, keyed
+            /// by the faces pointing towards them, see [`VisFace::EDGES`].
+            pub fn edge_neighbours(self) -> impl Iterator<Item = ([VisFace; 2], Self)> {
+                VisFace::EDGES.into_iter().map(move |faces| (faces, self + faces[0].offset() + faces[1].offset()))
+            }
+
+            /// The eight positions sharing only a corner with this one, keyed
+            /// by the faces pointing towards them, see [`VisFace::CORNERS`].
+            pub fn corner_neighbours(self) -> impl Iterator<Item = ([VisFace; 3], Self)> {
+                VisFace::CORNERS.into_iter().map(move |faces| (faces, self + faces[0].offset() + faces[1].offset() + faces[2].offset()))
+            }
+
+        }
+
+        /// Offsets the position, wrapping at the edge of the coordinate range.
+        /// The result isn't clipped.
+        impl$(<const $size: usize>)? Add<IVec3> for $name$(<$size>)? {
+            type Output = Self;
+
+            fn add(self, rhs: IVec3) -> Self {
+                Self::new(
+                    self.x.wrapping_add(rhs.x as $scalar),
+                    self.y.wrapping_add(rhs.y as $scalar),
+                    self.z.wrapping_add(rhs.z as $scalar),
+                )
+            }
+        }
+
+        impl$(<const $size: usize>)? Sub<IVec3> for $name$(<$size>)? {
+            type Output = Self;
+
+            fn sub(self, rhs: IVec3) -> Self {
+                Self::new(
+                    self.x.wrapping_sub(rhs.x as $scalar),
+                    self.y.wrapping_sub(rhs.y as $scalar),
+                    self.z.wrapping_sub(rhs.z as $scalar),
+                )
+            }
+        }
+
+        impl$(<const $size: usize>)? AddAssign<IVec3> for $name$(<$size>)? {
+            fn add_assign(&mut self, rhs: IVec3) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl$(<const $size: usize>)? SubAssign<IVec3> for $name$(<$size>)? {
+            fn sub_assign(&mut self, rhs: IVec3) {
+                *self = *self - rhs;
+            }
+        }
+
+        /// The offset from `rhs` to this position.
+        impl$(<const $size: usize>)? Sub for $name$(<$size>)? {
+            type Output = IVec3;
+
+            fn sub(self, rhs: Self) -> IVec3 {
+                self.as_ivec3().wrapping_sub(rhs.as_ivec3())
+            }
+        }
+    };
+}
+
+/// Masks coordinates with the type's `COORDINATE_MASK`, for the positions
+/// that wrap at a fixed range.
+macro_rules! impl_clip {
+    ($name:ident $(<$size:ident>)?) => {
+
+        impl$(<const $size: usize>)? $name$(<$size>)? {
 
             #[must_use]
             pub const fn new_unclipped(x: i16, y: i16, z: i16) -> Self {
@@ -25,11 +128,6 @@ macro_rules! impl_world_pos {
                 )
             }
 
-            #[must_use]
-            pub const fn new(x: i16, y: i16, z: i16) -> Self {
-                Self{x, y, z}
-            }
-
             #[must_use]
             pub const fn clip(self) -> Self {
                 Self::new(
@@ -39,21 +137,30 @@ macro_rules! impl_world_pos {
                 )
             }
 
-            #[must_use]
-            pub const fn as_ivec3(self) -> IVec3 {
-                IVec3::new(self.x as i32, self.y as i32, self.z as i32)
-            }
-
-            #[must_use]
-            pub const fn from_ivec3(other: IVec3) -> Self {
-                Self::new(other.x as i16, other.y as i16, other.z as i16)
-            }
-
         }
+
     };
 }
 
-impl_world_pos!(PosWorld);
+/// A position that can be split into a chunk and a position within it,
+/// letting world storage be addressed with either [`PosWorld`] or
+/// [`PosWorldWide`].
+pub trait WorldPosition: Copy + Eq + Hash + Add<IVec3, Output = Self> + Sub<IVec3, Output = Self> {
+
+    #[must_use]
+    fn from_chunk_and_block(chunk: PosChunk, block: PosBlock) -> Self;
+
+    #[must_use]
+    fn to_chunk_and_block(self) -> (PosChunk, PosBlock);
+
+    /// Offsets the position, wrapping at the edge of the coordinate range.
+    #[must_use]
+    fn with_offset(self, x: i16, y: i16, z: i16) -> Self;
+
+}
+
+impl_world_pos!(PosWorld: i16);
+impl_clip!(PosWorld);
 
 impl PosWorld {
 
@@ -61,20 +168,36 @@ impl PosWorld {
 
     #[must_use]
     pub const fn from_chunk_and_block(chunk: PosChunk, block: PosBlock) -> Self {
+        Self::from_chunk_and_block_sized(chunk, block)
+    }
+
+    #[must_use]
+    pub const fn to_chunk_and_block(self) -> (PosChunk, PosBlock) {
+        self.to_chunk_and_block_sized()
+    }
+
+    /// Joins a chunk position and a position within chunks `SIZE` voxels
+    /// across, see [`Self::from_chunk_and_block`].
+    #[must_use]
+    pub const fn from_chunk_and_block_sized<const SIZE: usize>(chunk: PosChunk, block: PosBlock<SIZE>) -> Self {
+        let bits = ChunkDimensions::<SIZE>::COORD_BITS;
         Self::new(
-            chunk.x << CHUNK_COORD_BITS | block.x,
-            chunk.y << CHUNK_COORD_BITS | block.y,
-            chunk.z << CHUNK_COORD_BITS | block.z,
+            chunk.x << bits | block.x,
+            chunk.y << bits | block.y,
+            chunk.z << bits | block.z,
         )
     }
 
+    /// Splits this position into a chunk position and a position within
+    /// chunks `SIZE` voxels across, see [`Self::to_chunk_and_block`].
     #[must_use]
-    pub const fn to_chunk_and_block(self) -> (PosChunk, PosBlock) {
+    pub const fn to_chunk_and_block_sized<const SIZE: usize>(self) -> (PosChunk, PosBlock<SIZE>) {
+        let bits = ChunkDimensions::<SIZE>::COORD_BITS;
         (
             PosChunk::new(
-                self.x >> CHUNK_COORD_BITS,
-                self.y >> CHUNK_COORD_BITS,
-                self.z >> CHUNK_COORD_BITS,
+                self.x >> bits,
+                self.y >> bits,
+                self.z >> bits,
             ),
             PosBlock::new_unclipped(
                 self.x,
@@ -84,42 +207,236 @@ impl PosWorld {
         )
     }
 
+    /// Offsets the position, wrapping at the edge of the coordinate range.
     #[must_use]
     pub const fn with_offset(self, x: i16, y: i16, z: i16) -> Self {
-        Self::new(self.x + x, self.y + y, self.z + z)
+        Self::new(self.x.wrapping_add(x), self.y.wrapping_add(y), self.z.wrapping_add(z))
+    }
+
+}
+
+impl WorldPosition for PosWorld {
+
+    fn from_chunk_and_block(chunk: PosChunk, block: PosBlock) -> Self {
+        Self::from_chunk_and_block(chunk, block)
+    }
+
+    fn to_chunk_and_block(self) -> (PosChunk, PosBlock) {
+        self.to_chunk_and_block()
+    }
+
+    fn with_offset(self, x: i16, y: i16, z: i16) -> Self {
+        self.with_offset(x, y, z)
     }
 
 }
 
-impl_world_pos!(PosChunk);
+impl TryFrom<PosWorldWide> for PosWorld {
+    type Error = core::num::TryFromIntError;
+
+    fn try_from(value: PosWorldWide) -> Result<Self, Self::Error> {
+        Ok(Self::new(
+            i16::try_from(value.x)?,
+            i16::try_from(value.y)?,
+            i16::try_from(value.z)?,
+        ))
+    }
+}
+
+impl_world_pos!(
+    /// A world position with 32-bit coordinates, for worlds wider than the
+    /// ±32k blocks of [`PosWorld`]. Chunk positions are still [`PosChunk`],
+    /// so only positions within ±32k chunks can be split into a chunk, see
+    /// [`Self::try_to_chunk_and_block`].
+    PosWorldWide: i32
+);
+
+impl PosWorldWide {
+
+    #[must_use]
+    pub const fn from_chunk_and_block(chunk: PosChunk, block: PosBlock) -> Self {
+        Self::from_chunk_and_block_sized(chunk, block)
+    }
+
+    /// Splits this position into a chunk and a position within it, see
+    /// [`Self::to_chunk_and_block_sized`].
+    #[must_use]
+    pub const fn to_chunk_and_block(self) -> (PosChunk, PosBlock) {
+        self.to_chunk_and_block_sized()
+    }
+
+    /// Splits this position like [`Self::to_chunk_and_block`], or `None` if
+    /// the chunk doesn't fit a [`PosChunk`].
+    #[must_use]
+    pub const fn try_to_chunk_and_block(self) -> Option<(PosChunk, PosBlock)> {
+        self.try_to_chunk_and_block_sized()
+    }
+
+    /// Joins a chunk position and a position within chunks `SIZE` voxels
+    /// across, see [`Self::from_chunk_and_block`].
+    #[must_use]
+    pub const fn from_chunk_and_block_sized<const SIZE: usize>(chunk: PosChunk, block: PosBlock<SIZE>) -> Self {
+        let bits = ChunkDimensions::<SIZE>::COORD_BITS;
+        Self::new(
+            (chunk.x as i32) << bits | block.x as i32,
+            (chunk.y as i32) << bits | block.y as i32,
+            (chunk.z as i32) << bits | block.z as i32,
+        )
+    }
+
+    /// Splits this position into a chunk position and a position within
+    /// chunks `SIZE` voxels across, flooring towards negative infinity.
+    ///
+    /// Panics in debug builds if the chunk doesn't fit a [`PosChunk`], and
+    /// wraps onto another chunk in release builds, see
+    /// [`Self::try_to_chunk_and_block_sized`].
+    #[must_use]
+    pub const fn to_chunk_and_block_sized<const SIZE: usize>(self) -> (PosChunk, PosBlock<SIZE>) {
+        debug_assert!(self.try_to_chunk_and_block_sized::<SIZE>().is_some(), "Chunk position out of range");
+        self.split::<SIZE>()
+    }
+
+    /// Splits this position like [`Self::to_chunk_and_block_sized`], or
+    /// `None` if the chunk doesn't fit a [`PosChunk`].
+    #[must_use]
+    pub const fn try_to_chunk_and_block_sized<const SIZE: usize>(self) -> Option<(PosChunk, PosBlock<SIZE>)> {
+        let (chunk, block) = self.split::<SIZE>();
+        let bits = ChunkDimensions::<SIZE>::COORD_BITS;
+        if chunk.x as i32 != self.x >> bits || chunk.y as i32 != self.y >> bits || chunk.z as i32 != self.z >> bits {
+            return None;
+        }
+        Some((chunk, block))
+    }
+
+    const fn split<const SIZE: usize>(self) -> (PosChunk, PosBlock<SIZE>) {
+        let bits = ChunkDimensions::<SIZE>::COORD_BITS;
+        let mask = PosBlock::<SIZE>::COORDINATE_MASK as i32;
+        (
+            PosChunk::new(
+                (self.x >> bits) as i16,
+                (self.y >> bits) as i16,
+                (self.z >> bits) as i16,
+            ),
+            PosBlock::new(
+                (self.x & mask) as i16,
+                (self.y & mask) as i16,
+                (self.z & mask) as i16,
+            )
+        )
+    }
+
+    /// Offsets the position, wrapping at the edge of the coordinate range.
+    #[must_use]
+    pub const fn with_offset(self, x: i32, y: i32, z: i32) -> Self {
+        Self::new(self.x.wrapping_add(x), self.y.wrapping_add(y), self.z.wrapping_add(z))
+    }
+
+}
+
+impl WorldPosition for PosWorldWide {
+
+    fn from_chunk_and_block(chunk: PosChunk, block: PosBlock) -> Self {
+        Self::from_chunk_and_block(chunk, block)
+    }
+
+    fn to_chunk_and_block(self) -> (PosChunk, PosBlock) {
+        self.to_chunk_and_block()
+    }
+
+    fn with_offset(self, x: i16, y: i16, z: i16) -> Self {
+        self.with_offset(x.into(), y.into(), z.into())
+    }
+
+}
+
+impl From<PosWorld> for PosWorldWide {
+    fn from(value: PosWorld) -> Self {
+        Self::new(value.x.into(), value.y.into(), value.z.into())
+    }
+}
+
+impl_world_pos!(PosChunk: i16);
+impl_clip!(PosChunk);
 
 impl PosChunk {
 
-    pub const COORDINATE_MASK: i16 = (u16::MAX >> CHUNK_COORD_BITS) as i16;
+    /// Mask of the chunk coordinates reachable from a [`PosWorld`] split into
+    /// [`CHUNK_SIZE`] chunks, see [`Self::coordinate_mask_sized`].
+    pub const COORDINATE_MASK: i16 = Self::coordinate_mask_sized::<CHUNK_SIZE>();
+
+    /// Mask of the chunk coordinates reachable from a [`PosWorld`] split into
+    /// chunks `SIZE` voxels across.
+    #[must_use]
+    pub const fn coordinate_mask_sized<const SIZE: usize>() -> i16 {
+        (u16::MAX >> ChunkDimensions::<SIZE>::COORD_BITS) as i16
+    }
 
 }
 
 
-impl_world_pos!(PosBlock);
+impl_world_pos!(PosBlock<SIZE>: i16);
+impl_clip!(PosBlock<SIZE>);
+
+impl<const SIZE: usize> PosBlock<SIZE> {
 
-impl PosBlock {
+    pub const COORDINATE_MASK: i16 = (SIZE - 1) as i16;
 
-    pub const COORDINATE_MASK: i16 = (CHUNK_SIZE - 1) as i16;
+    const COORD_BITS: usize = ChunkDimensions::<SIZE>::COORD_BITS;
 
     #[must_use]
     pub const fn from_idx(idx: usize) -> Self {
         Self {
-            x: (idx                            as i16) & Self::COORDINATE_MASK,
-            y: ((idx >>    CHUNK_COORD_BITS )  as i16) & Self::COORDINATE_MASK,
-            z: ((idx >> (2*CHUNK_COORD_BITS))  as i16) & Self::COORDINATE_MASK,
+            x: (idx                             as i16) & Self::COORDINATE_MASK,
+            y: ((idx >>    Self::COORD_BITS )  as i16) & Self::COORDINATE_MASK,
+            z: ((idx >> (2*Self::COORD_BITS))  as i16) & Self::COORDINATE_MASK,
         }
     }
 
     #[must_use]
     pub const fn to_idx(self) -> usize {
         let Self{x, y, z} = self;
-        (x as usize) | ((y as usize) << CHUNK_COORD_BITS) | ((z as usize) << (2*CHUNK_COORD_BITS))
+        (x as usize) | ((y as usize) << Self::COORD_BITS) | ((z as usize) << (2*Self::COORD_BITS))
     }
 
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_floors_negative_coordinates() {
+        let size = CHUNK_SIZE as i32;
+        let chunk_min = i32::from(i16::MIN)*size;
+        let chunk_max = i32::from(i16::MAX)*size + size - 1;
+        for v in [0, -1, -32, -33, 100_000, -100_000, chunk_min, chunk_max] {
+            let pos = PosWorldWide::new(v, -v.saturating_add(1), v / 3);
+            let (chunk, block) = pos.try_to_chunk_and_block().unwrap();
+            assert_eq!(chunk.as_ivec3(), IVec3::new(pos.x.div_euclid(size), pos.y.div_euclid(size), pos.z.div_euclid(size)), "{v}");
+            assert_eq!(block.as_ivec3(), IVec3::new(pos.x.rem_euclid(size), pos.y.rem_euclid(size), pos.z.rem_euclid(size)), "{v}");
+            assert_eq!(PosWorldWide::from_chunk_and_block(chunk, block), pos);
+
+            if let Ok(narrow) = PosWorld::try_from(pos) {
+                assert_eq!(narrow.to_chunk_and_block(), (chunk, block));
+            }
+        }
+    }
+
+    #[test]
+    fn wide_rejects_chunks_out_of_range() {
+        let size = CHUNK_SIZE as i32;
+        for v in [i32::MIN, i32::MAX, i32::from(i16::MIN)*size - 1, (i32::from(i16::MAX) + 1)*size] {
+            assert_eq!(PosWorldWide::new(v, 0, 0).try_to_chunk_and_block(), None, "{v}");
+            assert_eq!(PosWorldWide::new(0, 0, v).try_to_chunk_and_block(), None, "{v}");
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic = "Chunk position out of range"]
+    fn wide_split_out_of_range_panics() {
+        let _ = PosWorldWide::new(i32::MAX, 0, 0).to_chunk_and_block();
+    }
+
+}
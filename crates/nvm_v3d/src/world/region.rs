@@ -0,0 +1,276 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use glam::IVec3;
+
+use super::{PosBlock, PosChunk, PosWorld, CHUNK_SIZE};
+
+/// An axis aligned box of world positions, `min` and `max` inclusive. The
+/// corners are always ordered, with `min` at or below `max` on every axis.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegionWorld {
+    min: PosWorld,
+    max: PosWorld,
+}
+
+impl RegionWorld {
+
+    /// The smallest region containing both corners.
+    #[must_use]
+    pub const fn new(a: PosWorld, b: PosWorld) -> Self {
+        Self {
+            min: PosWorld::new(min_i16(a.x, b.x), min_i16(a.y, b.y), min_i16(a.z, b.z)),
+            max: PosWorld::new(max_i16(a.x, b.x), max_i16(a.y, b.y), max_i16(a.z, b.z)),
+        }
+    }
+
+    #[must_use]
+    pub const fn from_pos(pos: PosWorld) -> Self {
+        Self{ min: pos, max: pos }
+    }
+
+    /// The region covering every block of the chunk.
+    #[must_use]
+    pub const fn from_chunk(chunk: PosChunk) -> Self {
+        let mask = PosBlock::<CHUNK_SIZE>::COORDINATE_MASK;
+        Self {
+            min: PosWorld::from_chunk_and_block(chunk, PosBlock::new(0, 0, 0)),
+            max: PosWorld::from_chunk_and_block(chunk, PosBlock::new(mask, mask, mask)),
+        }
+    }
+
+    #[must_use]
+    pub const fn min(self) -> PosWorld {
+        self.min
+    }
+
+    #[must_use]
+    pub const fn max(self) -> PosWorld {
+        self.max
+    }
+
+    /// Number of positions along each axis.
+    #[must_use]
+    pub const fn size(self) -> IVec3 {
+        IVec3::new(
+            self.max.x as i32 - self.min.x as i32 + 1,
+            self.max.y as i32 - self.min.y as i32 + 1,
+            self.max.z as i32 - self.min.z as i32 + 1,
+        )
+    }
+
+    #[must_use]
+    pub const fn volume(self) -> usize {
+        let size = self.size();
+        size.x as usize * size.y as usize * size.z as usize
+    }
+
+    #[must_use]
+    pub const fn contains(self, pos: PosWorld) -> bool {
+        self.min.x <= pos.x && pos.x <= self.max.x &&
+        self.min.y <= pos.y && pos.y <= self.max.y &&
+        self.min.z <= pos.z && pos.z <= self.max.z
+    }
+
+    /// The region covered by both, if they overlap.
+    #[must_use]
+    pub const fn intersection(self, other: Self) -> Option<Self> {
+        let min = PosWorld::new(max_i16(self.min.x, other.min.x), max_i16(self.min.y, other.min.y), max_i16(self.min.z, other.min.z));
+        let max = PosWorld::new(min_i16(self.max.x, other.max.x), min_i16(self.max.y, other.max.y), min_i16(self.max.z, other.max.z));
+        if min.x > max.x || min.y > max.y || min.z > max.z {
+            return None;
+        }
+        Some(Self{ min, max })
+    }
+
+    /// The smallest region containing both.
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self {
+            min: PosWorld::new(min_i16(self.min.x, other.min.x), min_i16(self.min.y, other.min.y), min_i16(self.min.z, other.min.z)),
+            max: PosWorld::new(max_i16(self.max.x, other.max.x), max_i16(self.max.y, other.max.y), max_i16(self.max.z, other.max.z)),
+        }
+    }
+
+    /// Iterates every position in the region, X fastest then Y then Z.
+    pub fn iter(self) -> impl Iterator<Item = PosWorld> {
+        let Self{ min, max } = self;
+        (min.z..=max.z).flat_map(move |z|
+            (min.y..=max.y).flat_map(move |y|
+                (min.x..=max.x).map(move |x| PosWorld::new(x, y, z))
+            )
+        )
+    }
+
+    /// Splits the region at chunk borders, iterating each chunk it touches
+    /// with the part of the region inside that chunk.
+    pub fn chunks(self) -> impl Iterator<Item = (PosChunk, RegionBlock)> {
+        let (min_chunk, min_block) = self.min.to_chunk_and_block();
+        let (max_chunk, max_block) = self.max.to_chunk_and_block();
+        RegionChunk::new(min_chunk, max_chunk).iter().map(move |chunk| {
+            let mask = PosBlock::<CHUNK_SIZE>::COORDINATE_MASK;
+            let lo = |c: i16, min_c: i16, block: i16| if c == min_c { block } else { 0    };
+            let hi = |c: i16, max_c: i16, block: i16| if c == max_c { block } else { mask };
+            (chunk, RegionBlock::new(
+                PosBlock::new(lo(chunk.x, min_chunk.x, min_block.x), lo(chunk.y, min_chunk.y, min_block.y), lo(chunk.z, min_chunk.z, min_block.z)),
+                PosBlock::new(hi(chunk.x, max_chunk.x, max_block.x), hi(chunk.y, max_chunk.y, max_block.y), hi(chunk.z, max_chunk.z, max_block.z)),
+            ))
+        })
+    }
+
+}
+
+/// An axis aligned box of chunk positions, `min` and `max` inclusive. The
+/// corners are always ordered, like [`RegionWorld`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegionChunk {
+    min: PosChunk,
+    max: PosChunk,
+}
+
+impl RegionChunk {
+
+    /// The smallest region containing both corners.
+    #[must_use]
+    pub const fn new(a: PosChunk, b: PosChunk) -> Self {
+        Self {
+            min: PosChunk::new(min_i16(a.x, b.x), min_i16(a.y, b.y), min_i16(a.z, b.z)),
+            max: PosChunk::new(max_i16(a.x, b.x), max_i16(a.y, b.y), max_i16(a.z, b.z)),
+        }
+    }
+
+    #[must_use]
+    pub const fn min(self) -> PosChunk {
+        self.min
+    }
+
+    #[must_use]
+    pub const fn max(self) -> PosChunk {
+        self.max
+    }
+
+    #[must_use]
+    pub const fn contains(self, pos: PosChunk) -> bool {
+        self.min.x <= pos.x && pos.x <= self.max.x &&
+        self.min.y <= pos.y && pos.y <= self.max.y &&
+        self.min.z <= pos.z && pos.z <= self.max.z
+    }
+
+    /// Iterates every chunk in the region, X fastest then Y then Z.
+    pub fn iter(self) -> impl Iterator<Item = PosChunk> {
+        let Self{ min, max } = self;
+        (min.z..=max.z).flat_map(move |z|
+            (min.y..=max.y).flat_map(move |y|
+                (min.x..=max.x).map(move |x| PosChunk::new(x, y, z))
+            )
+        )
+    }
+
+}
+
+/// An axis aligned box of positions within a chunk, `min` and `max`
+/// inclusive. The corners are always ordered, like [`RegionWorld`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegionBlock {
+    min: PosBlock,
+    max: PosBlock,
+}
+
+impl RegionBlock {
+
+    /// The region covering every block of a chunk.
+    pub const ALL: Self = {
+        let mask = PosBlock::<CHUNK_SIZE>::COORDINATE_MASK;
+        Self{ min: PosBlock::new(0, 0, 0), max: PosBlock::new(mask, mask, mask) }
+    };
+
+    /// The smallest region containing both corners.
+    #[must_use]
+    pub const fn new(a: PosBlock, b: PosBlock) -> Self {
+        Self {
+            min: PosBlock::new(min_i16(a.x, b.x), min_i16(a.y, b.y), min_i16(a.z, b.z)),
+            max: PosBlock::new(max_i16(a.x, b.x), max_i16(a.y, b.y), max_i16(a.z, b.z)),
+        }
+    }
+
+    #[must_use]
+    pub const fn min(self) -> PosBlock {
+        self.min
+    }
+
+    #[must_use]
+    pub const fn max(self) -> PosBlock {
+        self.max
+    }
+
+    #[must_use]
+    pub const fn volume(self) -> usize {
+        (self.max.x - self.min.x + 1) as usize *
+        (self.max.y - self.min.y + 1) as usize *
+        (self.max.z - self.min.z + 1) as usize
+    }
+
+    #[must_use]
+    pub const fn contains(self, pos: PosBlock) -> bool {
+        self.min.x <= pos.x && pos.x <= self.max.x &&
+        self.min.y <= pos.y && pos.y <= self.max.y &&
+        self.min.z <= pos.z && pos.z <= self.max.z
+    }
+
+    /// Iterates every position in the region, X fastest then Y then Z.
+    pub fn iter(self) -> impl Iterator<Item = PosBlock> {
+        let Self{ min, max } = self;
+        (min.z..=max.z).flat_map(move |z|
+            (min.y..=max.y).flat_map(move |y|
+                (min.x..=max.x).map(move |x| PosBlock::new(x, y, z))
+            )
+        )
+    }
+
+}
+
+const fn min_i16(a: i16, b: i16) -> i16 {
+    if a < b { a } else { b }
+}
+
+const fn max_i16(a: i16, b: i16) -> i16 {
+    if a > b { a } else { b }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_orders_corners() {
+        let region = RegionWorld::new(PosWorld::new(5, -2, 7), PosWorld::new(-3, 4, 7));
+        assert_eq!(region.min(), PosWorld::new(-3, -2, 7));
+        assert_eq!(region.max(), PosWorld::new(5, 4, 7));
+        assert_eq!(region.size(), IVec3::new(9, 7, 1));
+        assert_eq!(region.volume(), 63);
+        assert_eq!(region.iter().count(), 63);
+
+        let chunks = RegionChunk::new(PosChunk::new(1, 0, -1), PosChunk::new(-1, 0, 1));
+        assert_eq!((chunks.min(), chunks.max()), (PosChunk::new(-1, 0, -1), PosChunk::new(1, 0, 1)));
+        assert_eq!(chunks.iter().count(), 9);
+
+        let blocks = RegionBlock::new(PosBlock::new(4, 1, 0), PosBlock::new(2, 1, 3));
+        assert_eq!((blocks.min(), blocks.max()), (PosBlock::new(2, 1, 0), PosBlock::new(4, 1, 3)));
+        assert_eq!(blocks.volume(), 12);
+        assert_eq!(blocks.iter().count(), 12);
+    }
+
+    #[test]
+    fn chunks_cover_region() {
+        let region = RegionWorld::new(PosWorld::new(-40, 3, 30), PosWorld::new(70, 5, 33));
+        let mut volume = 0;
+        for (chunk, blocks) in region.chunks() {
+            assert!(RegionWorld::from_chunk(chunk).intersection(region).is_some());
+            for block in blocks.iter() {
+                assert!(region.contains(PosWorld::from_chunk_and_block(chunk, block)));
+            }
+            volume += blocks.volume();
+        }
+        assert_eq!(volume, region.volume());
+    }
+
+}
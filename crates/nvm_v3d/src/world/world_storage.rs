@@ -0,0 +1,182 @@
+// Copyright 2024 Natalie Baker // AGPLv3 //
+
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+use crate::tiles::{TileIdentifier, TileRegistry};
+
+use super::{ChunkStorage, DirtySubChunks, PosChunk, WorldPosition};
+
+#[derive(Debug, Default)]
+pub struct World(HashMap<PosChunk, ChunkStorage>);
+
+impl World {
+
+    #[must_use]
+    pub fn get(&self, pos: impl WorldPosition) -> TileIdentifier {
+        let (pos_chunk, pos_block) = pos.to_chunk_and_block();
+        self.0.get(&pos_chunk).map_or(TileIdentifier::DEFAULT, |chunk| chunk.get(pos_block))
+    }
+
+    /// Sets the tile at the given position, creating the chunk if it isn't
+    /// loaded yet. Returns the chunk that was modified, or `None` if the tile
+    /// was already set. Setting [`TileIdentifier::DEFAULT`] in an unloaded
+    /// chunk doesn't create it.
+    ///
+    /// Edits on a chunk border also mark the affected sub-chunks of loaded
+    /// neighbouring chunks as dirty.
+    pub fn set(&mut self, pos: impl WorldPosition, id: TileIdentifier, registry: &TileRegistry) -> Option<PosChunk> {
+        let (pos_chunk, pos_block) = pos.to_chunk_and_block();
+        let chunk = match self.0.get_mut(&pos_chunk) {
+            Some(chunk) if chunk.get(pos_block) == id => return None,
+            Some(chunk) => chunk,
+            None if id == TileIdentifier::DEFAULT => return None,
+            None => self.get_or_create_chunk(pos_chunk),
+        };
+        chunk.update(pos_block, id, registry);
+
+        DirtySubChunks::for_each_affected_offset(pos_block, |[x, y, z]| {
+            let (neighbour, block) = pos.with_offset(x, y, z).to_chunk_and_block();
+            if neighbour == pos_chunk {
+                return;
+            }
+            if let Some(chunk) = self.0.get_mut(&neighbour) {
+                chunk.mark_dirty(DirtySubChunks::sub_chunk_of(block));
+            }
+        });
+        Some(pos_chunk)
+    }
+
+    /// Returns and clears the dirty sub-chunks of every chunk with changes,
+    /// see [`ChunkStorage::drain_dirty`]. Chunks not yet reached when the
+    /// iterator is dropped stay dirty.
+    pub fn drain_dirty(&mut self) -> impl Iterator<Item = (PosChunk, DirtySubChunks)> + '_ {
+        self.0.iter_mut().filter_map(|(&pos, chunk)| {
+            let dirty = chunk.drain_dirty();
+            (!dirty.is_empty()).then_some((pos, dirty))
+        })
+    }
+
+    /// Applies a batch of updates, adding every chunk that was modified to
+    /// `touched`. Returns the number of updates that changed a tile.
+    pub fn set_batched(
+        &mut self,
+        updates:  impl IntoIterator<Item = (impl WorldPosition, TileIdentifier)>,
+        registry: &TileRegistry,
+        touched:  &mut HashSet<PosChunk>,
+    ) -> usize {
+        let mut update_count = 0;
+        for (pos, id) in updates {
+            if let Some(pos_chunk) = self.set(pos, id, registry) {
+                touched.insert(pos_chunk);
+                update_count += 1;
+            }
+        }
+        update_count
+    }
+
+}
+
+impl World {
+
+    pub fn get_or_create_chunk(&mut self, pos_chunk: PosChunk) -> &mut ChunkStorage {
+        match self.0.entry(pos_chunk) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => v.insert(ChunkStorage::new_empty()),
+        }
+    }
+
+    #[must_use]
+    pub fn get_chunk(&self, pos_chunk: PosChunk) -> Option<&ChunkStorage> {
+        self.0.get(&pos_chunk)
+    }
+
+    #[must_use]
+    pub fn get_chunk_mut(&mut self, pos_chunk: PosChunk) -> Option<&mut ChunkStorage> {
+        self.0.get_mut(&pos_chunk)
+    }
+
+    pub fn insert_chunk(&mut self, pos_chunk: PosChunk, chunk: ChunkStorage) -> Option<ChunkStorage> {
+        self.0.insert(pos_chunk, chunk)
+    }
+
+    pub fn remove_chunk(&mut self, pos_chunk: PosChunk) -> Option<ChunkStorage> {
+        self.0.remove(&pos_chunk)
+    }
+
+    #[must_use]
+    pub fn is_chunk_loaded(&self, pos_chunk: PosChunk) -> bool {
+        self.0.contains_key(&pos_chunk)
+    }
+
+    #[must_use]
+    pub fn chunk_count(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn iter_chunks(&self) -> impl Iterator<Item = (PosChunk, &ChunkStorage)> {
+        self.0.iter().map(|(&pos, chunk)| (pos, chunk))
+    }
+
+    pub fn iter_chunks_mut(&mut self) -> impl Iterator<Item = (PosChunk, &mut ChunkStorage)> {
+        self.0.iter_mut().map(|(&pos, chunk)| (pos, chunk))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tiles::TileDefinition, world::{PosWorld, RegionChunk}};
+
+    use super::*;
+
+    #[test]
+    fn edits_dirty_loaded_neighbours() {
+        let mut registry = TileRegistry::new();
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+
+        let mut world = World::default();
+        let missing = PosChunk::new(-1, -1, -1);
+        for pos in RegionChunk::new(missing, PosChunk::new(0, 0, 0)).iter().filter(|&pos| pos != missing) {
+            world.get_or_create_chunk(pos);
+        }
+        assert_eq!(world.drain_dirty().count(), 7);
+
+        assert_eq!(world.set(PosWorld::new(0, 0, 0), stone, &registry), Some(PosChunk::new(0, 0, 0)));
+        let dirty: HashMap<_, _> = world.drain_dirty().collect();
+        assert_eq!(dirty.len(), 7);
+        assert!(!world.is_chunk_loaded(missing));
+        for (pos, sub_chunks) in dirty {
+            let expected = [pos.x, pos.y, pos.z].map(|v| if v < 0 { 3 } else { 0 });
+            assert_eq!(sub_chunks.iter().collect::<Vec<_>>(), [expected], "{pos:?}");
+        }
+
+        assert_eq!(world.set(PosWorld::new(0, 0, 0), stone, &registry), None);
+        assert_eq!(world.drain_dirty().count(), 0);
+    }
+
+    #[test]
+    fn unchanged_writes_touch_nothing() {
+        let mut registry = TileRegistry::new();
+        let stone = registry.register("test:stone", TileDefinition::new_opaque("Stone")).unwrap();
+
+        let mut world = World::default();
+        assert_eq!(world.set(PosWorld::new(40, 0, 0), TileIdentifier::DEFAULT, &registry), None);
+        assert_eq!(world.chunk_count(), 0);
+
+        let mut touched = HashSet::new();
+        let updates = [
+            (PosWorld::new(1, 2, 3), stone),
+            (PosWorld::new(1, 2, 3), stone),
+            (PosWorld::new(40, 0, 0), TileIdentifier::DEFAULT),
+            (PosWorld::new(-1, 0, 0), stone),
+        ];
+        assert_eq!(world.set_batched(updates, &registry, &mut touched), 2);
+        assert_eq!(touched, HashSet::from([PosChunk::new(0, 0, 0), PosChunk::new(-1, 0, 0)]));
+        assert_eq!(world.chunk_count(), 2);
+
+        touched.clear();
+        assert_eq!(world.set_batched([(PosWorld::new(1, 2, 3), stone)], &registry, &mut touched), 0);
+        assert!(touched.is_empty());
+    }
+
+}
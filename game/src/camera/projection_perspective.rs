@@ -24,6 +24,7 @@ impl ProjectionPerspective {
         }
     }
 
+    #[allow(dead_code)]
     pub fn set_fov(&mut self, fov: f32) {
         self.fov = fov;
         self.matrix.set(None);
@@ -34,28 +35,34 @@ impl ProjectionPerspective {
         self.matrix.set(None);
     }
 
+    #[allow(dead_code)]
     pub fn set_near(&mut self, near: f32) {
         self.near = near;
         self.matrix.set(None);
     }
 
+    #[allow(dead_code)]
     pub fn set_far(&mut self, far: f32) {
         self.far = far;
         self.matrix.set(None);
     }
 
+    #[allow(dead_code)]
     pub const fn fov(&self) -> f32 {
         self.fov
     }
 
+    #[allow(dead_code)]
     pub const fn aspect(&self) -> f32 {
         self.aspect
     }
 
+    #[allow(dead_code)]
     pub const fn near(&self) -> f32 {
         self.near
     }
 
+    #[allow(dead_code)]
     pub const fn far(&self) -> f32 {
         self.far
     }
@@ -12,6 +12,7 @@ pub struct Transform {
 
 impl Transform {
 
+    #[allow(dead_code)]
     pub const fn new() -> Self {
         Self{
             position: Vec3::ZERO,
@@ -4,7 +4,7 @@ use winit::{error::EventLoopError, event::WindowEvent, event_loop::{ActiveEventL
 use wgpu::util::DeviceExt;
 
 use nvm_app::{ActiveApplication, ApplicationShim, WGPUConfig, WGPUState};
-use nvm_v3d::{lighting::{light_blocklight_raise_batched, LightStorageWorld}, tiles::TileIdentifier, world::{PosChunk, PosWorld}};
+use nvm_v3d::{lighting::{light_blocklight_raise_batched, LightStorageWorld}, meshing::FaceVisibilityProvider, tiles::{TileDefinition, TileRegistry}, world::{ChunkNeighbourhood, PosChunk, PosWorld, UnloadedChunkMode}};
 
 mod pipeline_chunk;
 mod wgpu_util;
@@ -17,18 +17,24 @@ use camera::{Camera, CameraUniform, ProjectionPerspective, Transform};
 use glam::{Vec2, Vec3};
 use pipeline_chunk::PipelineChunk;
 use texture_group::TextureInfo;
-use vox_util::{mesh_chunk, read_vox, write_lighting_data_to_image};
+use vox_util::{mesh_chunk, mesh_shapes, read_vox, write_lighting_data_to_image};
 
 fn main() -> Result<(), EventLoopError> {
     env_logger::init();
 
     let args: Vec<_> = std::env::args().skip(1).collect();
-    let chunk = read_vox(&args[0]);
-    let mesh_data = mesh_chunk(&chunk);
+    let mut registry = TileRegistry::new();
+    let tile_voxel = registry.register("game:voxel", TileDefinition::new_opaque("Voxel")).unwrap();
+
+    let world = read_vox(&args[0], tile_voxel, &registry);
+    let neighbourhood = ChunkNeighbourhood::from_world(&world, PosChunk::new(0, 0, 0), UnloadedChunkMode::Empty).unwrap();
+    let (mesh_data,  tile_data      ) = mesh_chunk(&neighbourhood);
+    let (shape_data, shape_tile_data) = mesh_shapes(&neighbourhood, &registry);
     
     let mut light_data = LightStorageWorld::default();
     light_blocklight_raise_batched(
-        &mut |pos, _| if chunk.get(pos.to_chunk_and_block().1) == TileIdentifier::DEFAULT { 0 } else { u8::MAX },
+        &world,
+        &registry,
         &mut light_data, 
         &[
             (PosWorld::new(12, 12, 15), [31,  0,  0]),
@@ -43,6 +49,9 @@ fn main() -> Result<(), EventLoopError> {
     let event_loop = EventLoop::new().unwrap();
     let mut app = ApplicationShim::<Application, ApplicationConfig>::new(ApplicationConfig{
         mesh_data,
+        tile_data,
+        shape_data,
+        shape_tile_data,
         light_data: Vec::from(light_data.get_chunk(PosChunk::new(0, 0, 0)).unwrap().get_data()),
     });
     event_loop.run_app(&mut app)
@@ -50,6 +59,9 @@ fn main() -> Result<(), EventLoopError> {
 
 pub struct ApplicationConfig {
     pub mesh_data: Vec<u32>,
+    pub tile_data: Vec<u32>,
+    pub shape_data: Vec<u32>,
+    pub shape_tile_data: Vec<u32>,
     pub light_data: Vec<[u8; 4]>,
 }
 
@@ -58,7 +70,8 @@ pub struct Application {
 
     pipeline_chunk: PipelineChunk,
     
-    mesh_buffer: wgpu::Buffer,
+    face_count:  u32,
+    shape_count: u32,
 
     chunk_bind_group: wgpu::BindGroup,
 
@@ -76,10 +89,12 @@ impl ActiveApplication<ApplicationConfig> for Application {
     async fn new(event_loop: &ActiveEventLoop, config: &ApplicationConfig) -> Self {
         let wgpu = WGPUState::new(event_loop, &mut WGPUConfig::default()).await;
 
+        // Storage bindings can't be empty, the draws use the real counts
+        let face_count  = config.mesh_data.len() as u32;
         let mesh_buffer = wgpu.device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("mesh_buffer"),
-                contents: bytemuck::cast_slice(&config.mesh_data),
+                contents: bytemuck::cast_slice(if face_count == 0 { &[0] } else { &config.mesh_data }),
                 usage: wgpu::BufferUsages::STORAGE,
             }
         );
@@ -90,6 +105,28 @@ impl ActiveApplication<ApplicationConfig> for Application {
                 usage: wgpu::BufferUsages::STORAGE,
             }
         );
+        let tile_buffer = wgpu.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("tile_buffer"),
+                contents: bytemuck::cast_slice(if face_count == 0 { &[0] } else { &config.tile_data }),
+                usage: wgpu::BufferUsages::STORAGE,
+            }
+        );
+        let shape_count = config.shape_data.len() as u32;
+        let shape_buffer = wgpu.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("shape_buffer"),
+                contents: bytemuck::cast_slice(if shape_count == 0 { &[0] } else { &config.shape_data }),
+                usage: wgpu::BufferUsages::STORAGE,
+            }
+        );
+        let shape_tile_buffer = wgpu.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("shape_tile_buffer"),
+                contents: bytemuck::cast_slice(if shape_count == 0 { &[0] } else { &config.shape_tile_data }),
+                usage: wgpu::BufferUsages::STORAGE,
+            }
+        );
         let chunk_bind_group_layout = wgpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("chunk_bind_group_layout"),
             entries: &[
@@ -113,6 +150,36 @@ impl ActiveApplication<ApplicationConfig> for Application {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
         let chunk_bind_group = wgpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -127,6 +194,18 @@ impl ActiveApplication<ApplicationConfig> for Application {
                     binding: 1,
                     resource: light_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: tile_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: shape_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: shape_tile_buffer.as_entire_binding(),
+                },
             ],
         });
 
@@ -165,7 +244,13 @@ impl ActiveApplication<ApplicationConfig> for Application {
             ],
         });
         
-        let pipeline_chunk = PipelineChunk::new(&wgpu.device, wgpu.config.format, camera_bind_group_layout, chunk_bind_group_layout);
+        let pipeline_chunk = PipelineChunk::new(
+            &wgpu.device,
+            wgpu.config.format,
+            camera_bind_group_layout,
+            chunk_bind_group_layout,
+            <ChunkNeighbourhood as FaceVisibilityProvider>::VERTEX_LAYOUT,
+        );
 
         let depth_texture = TextureInfo::create_depth_texture(&wgpu.device, &wgpu.config, "depth_texture");
 
@@ -175,7 +260,8 @@ impl ActiveApplication<ApplicationConfig> for Application {
             camera,
             camera_buffer,
             camera_bind_group,
-            mesh_buffer,
+            face_count,
+            shape_count,
             chunk_bind_group,
             depth_texture,
             t: 0.0,
@@ -257,7 +343,9 @@ impl Application {
             render_pass.set_pipeline(&self.pipeline_chunk.pipeline);
             render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
             render_pass.set_bind_group(1, &self.chunk_bind_group, &[]);
-            render_pass.draw(0..((self.mesh_buffer.size() as u32)*6), 0..1);
+            render_pass.draw(0..(self.face_count*6), 0..1);
+            render_pass.set_pipeline(&self.pipeline_chunk.pipeline_shapes);
+            render_pass.draw(0..(self.shape_count*6), 0..1);
         }
 
         // submit will accept anything that implements IntoIter
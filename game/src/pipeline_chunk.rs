@@ -1,20 +1,30 @@
 // Copyright 2024 Natalie Baker // AGPLv3 //
 
+use std::collections::HashMap;
+
+use nvm_v3d::meshing::VertexLayout;
+
 use crate::wgpu_util::{ShaderModuleExt, PRIMITIVE_STATE_TRIANGLES};
 
 pub struct PipelineChunk {
     pub pipeline: wgpu::RenderPipeline,
+    /// Draws shaped voxels from the same bind groups, see `vs_shape`.
+    pub pipeline_shapes: wgpu::RenderPipeline,
 }
 
 impl PipelineChunk {
 
+    /// Draws faces encoded in the given layout, which must match the meshes
+    /// bound to the vertex bind group.
     pub fn new(
         device: &wgpu::Device,
         target_format: wgpu::TextureFormat,
         camera_bind_group_layout: wgpu::BindGroupLayout,
         vertex_bind_group_layout: wgpu::BindGroupLayout,
+        layout: VertexLayout,
     ) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!("pipeline_chunk.wgsl"));
+        let constants = HashMap::from([(String::from("coord_bits"), f64::from(layout.coord_bits()))]);
 
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Chunk Layout"),
@@ -25,10 +35,16 @@ impl PipelineChunk {
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline Chunk"),
+        let create_pipeline = |label: &str, entry_point: &str| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
             layout: Some(&render_pipeline_layout),
-            vertex: shader.create_vertex_state("vs_main", &[]),
+            vertex: wgpu::VertexState {
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+                ..shader.create_vertex_state(entry_point, &[])
+            },
             fragment: Some(shader.create_fragment_state("fs_main", &[Some(wgpu::ColorTargetState {
                 format: target_format,
                 blend: Some(wgpu::BlendState::REPLACE),
@@ -51,7 +67,8 @@ impl PipelineChunk {
         });
 
         Self { 
-            pipeline: render_pipeline 
+            pipeline:        create_pipeline("Render Pipeline Chunk",        "vs_main"),
+            pipeline_shapes: create_pipeline("Render Pipeline Chunk Shapes", "vs_shape"),
         }
     }
 
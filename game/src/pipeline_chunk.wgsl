@@ -15,6 +15,17 @@ struct CameraUniform {
 @group(0) @binding(0) var<uniform> camera: CameraUniform;
 @group(1) @binding(0) var<storage, read> faces: array<u32>;
 @group(1) @binding(1) var<storage, read> light: array<u32>;
+@group(1) @binding(2) var<storage, read> tiles: array<u32>;
+@group(1) @binding(3) var<storage, read> shape_faces: array<u32>;
+@group(1) @binding(4) var<storage, read> shape_tiles: array<u32>;
+
+// Bits per position of the encoded faces, the `VertexLayout::coord_bits` of
+// the chunk size meshed
+override coord_bits: u32 = 5u;
+
+fn get_coord_mask() -> u32 {
+    return (1u << coord_bits) - 1u;
+}
 
 @vertex
 fn vs_main(
@@ -24,7 +35,7 @@ fn vs_main(
     let vert_index = in_vertex_index % 6;
 
     let face_data  = decode_voxel_face_data(face_index);
-    let uv         = get_voxel_face_uv(face_data.face, vert_index);
+    let uv         = get_voxel_face_uv(face_data, vert_index);
     let basis      = get_voxel_face_basis(face_data.face);
     let vertex_pos = calc_voxel_face_vertex(face_data, basis, uv);
 
@@ -34,23 +45,55 @@ fn vs_main(
 
     // out.colour = vec3<f32>(face_data.pos_block)/32.0;
 
-    let light_pos = face_data.pos_block - basis[2];
+    out.colour  = get_light_colour(face_data.pos_block - basis[2]) * get_tile_colour(tiles[face_index]);
+    out.colour *= 0.4 + 0.2*f32(get_voxel_face_ao(face_data, uv));
+    
+    return out;
+}
+
+@vertex
+fn vs_shape(
+    @builtin(vertex_index) in_vertex_index: u32,
+) -> VertexOutput {
+    let face_index = in_vertex_index / 6;
+    let vert_index = in_vertex_index % 6;
+
+    let shape_data = decode_shape_face_data(face_index);
+    let quad       = get_shape_face_quad(shape_data);
+    let uv         = get_shape_face_uv(quad, vert_index);
+    let vertex_pos = vec3<f32>(shape_data.pos_block) + 0.5*vec3<f32>(quad.base + quad.u*uv.x + quad.v*uv.y);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(vertex_pos, 1.0);
+    out.normal = quad.normal;
+
+    // Box sides are lit from the voxel in front, like cube faces, and the
+    // diagonals from the voxel they stand in
+    var light_pos = shape_data.pos_block;
+    if (shape_data.face < 6) {
+        light_pos -= get_voxel_face_basis(shape_data.face)[2];
+    }
+    out.colour = get_light_colour(light_pos) * get_tile_colour(shape_tiles[face_index]);
+
+    return out;
+}
+
+fn get_light_colour(light_pos: vec3<i32>) -> vec3<f32> {
     let light_x = u32(light_pos.x);
     let light_y = u32(light_pos.y);
     let light_z = u32(light_pos.z);
-    if (light_x < 0 || light_x >= 32 || light_y < 0 || light_y >= 32 || light_z < 0 || light_z >= 32) {
-        out.colour = vec3<f32>(0.0, 0.0, 0.0);
-    } else {
-        let light_idx = light_x + (light_y << 5) + (light_z << 10);
-        let light_val = light[light_idx];
-        let r = f32((light_val >>  0) & 0xFF)/32.0;
-        let g = f32((light_val >>  8) & 0xFF)/32.0;
-        let b = f32((light_val >> 16) & 0xFF)/32.0;
-        // let lum = f32(light_idx)/(32.0*32.0*32.0);
-        out.colour = vec3<f32>(r, g, b);
+    let size    = 1u << coord_bits;
+    if (light_x < 0 || light_x >= size || light_y < 0 || light_y >= size || light_z < 0 || light_z >= size) {
+        return vec3<f32>(0.0, 0.0, 0.0);
     }
-    
-    return out;
+
+    let light_idx = light_x + (light_y << coord_bits) + (light_z << (2u*coord_bits));
+    let light_val = light[light_idx];
+    let r = f32((light_val >>  0) & 0xFF)/32.0;
+    let g = f32((light_val >>  8) & 0xFF)/32.0;
+    let b = f32((light_val >> 16) & 0xFF)/32.0;
+    // let lum = f32(light_idx)/(32.0*32.0*32.0);
+    return vec3<f32>(r, g, b);
 }
 
 // // /////////////// // //
@@ -61,18 +104,29 @@ struct VoxelFaceData {
     pos_face:  vec3<i32>,
     pos_block: vec3<i32>,
     face:      u32,
+    size:      vec2<i32>,
+    ao:        u32,
 }
 
 fn decode_voxel_face_data(face_index: u32) -> VoxelFaceData {
     let face = faces[face_index];
 
+    let mask = get_coord_mask();
+    let base = 3u*coord_bits;
+
     var result: VoxelFaceData;
     result.pos_face = vec3<i32>(
-        i32( face        & 0x1F),
-        i32((face >>  5) & 0x1F),
-        i32((face >> 10) & 0x1F)
+        i32( face                     & mask),
+        i32((face >>     coord_bits)  & mask),
+        i32((face >> (2u*coord_bits)) & mask)
+    );
+    result.face = (face >> base) & 0x07;
+    result.size = vec2<i32>(
+        i32((face >> (base + 3u)) & 0x07) + 1,
+        i32((face >> (base + 6u)) & 0x07) + 1
     );
-    result.face = (face >> 15) & 0x0F;
+    // Layouts without room for occlusion leave every corner unoccluded
+    result.ao = select(0xFFu, (face >> (base + 9u)) & 0xFF, base + 17u <= 32u);
 
     var pos_block = array<vec3<i32>, 6>(
         result.pos_face.zyx,
@@ -87,7 +141,7 @@ fn decode_voxel_face_data(face_index: u32) -> VoxelFaceData {
     return result;
 }
 
-fn get_voxel_face_uv(face: u32, vert_index: u32) -> vec2<i32> {
+fn get_voxel_face_uv(face_data: VoxelFaceData, vert_index: u32) -> vec2<i32> {
     var uvs = array<vec2<i32>, 6>(
         vec2<i32>(0, 0),
         vec2<i32>(1, 0),
@@ -97,7 +151,28 @@ fn get_voxel_face_uv(face: u32, vert_index: u32) -> vec2<i32> {
         vec2<i32>(1, 0),
     );
 
-    return select(uvs[vert_index].xy, uvs[vert_index].yx, face >= 3);
+    // Split along the brighter diagonal so occlusion interpolates evenly
+    var uvs_flipped = array<vec2<i32>, 6>(
+        vec2<i32>(0, 0),
+        vec2<i32>(1, 0),
+        vec2<i32>(1, 1),
+        vec2<i32>(0, 0),
+        vec2<i32>(1, 1),
+        vec2<i32>(0, 1),
+    );
+
+    let ao_00 = (face_data.ao     ) & 0x03;
+    let ao_10 = (face_data.ao >> 2) & 0x03;
+    let ao_01 = (face_data.ao >> 4) & 0x03;
+    let ao_11 = (face_data.ao >> 6) & 0x03;
+    let uv = select(uvs[vert_index], uvs_flipped[vert_index], ao_00 + ao_11 > ao_10 + ao_01);
+
+    return select(uv.xy, uv.yx, face_data.face >= 3);
+}
+
+fn get_voxel_face_ao(face_data: VoxelFaceData, uv: vec2<i32>) -> u32 {
+    let corner = u32(uv.x + uv.y*2);
+    return (face_data.ao >> (corner*2)) & 0x03;
 }
 
 fn get_voxel_face_basis(face: u32) -> array<vec3<i32>, 3> {
@@ -138,7 +213,120 @@ fn get_voxel_face_basis(face: u32) -> array<vec3<i32>, 3> {
 fn calc_voxel_face_vertex(face_data: VoxelFaceData, basis: array<vec3<i32>, 3>, uv: vec2<i32>) -> vec3<i32> {
     let face_offset = abs(basis[0])*face_data.pos_face.x + abs(basis[1])*face_data.pos_face.y + abs(basis[2])*face_data.pos_face.z;
     let offset = vec3<i32>(1, 1, 1) - component_max_3(basis[0] + basis[1] + basis[2], 0);
-    return face_offset + offset + basis[0]*uv.x + basis[1]*uv.y;
+    // Axes running in the negative direction start from the far side of the quad
+    let offset_size = component_max_3(-basis[0], 0)*(face_data.size.x - 1) + component_max_3(-basis[1], 0)*(face_data.size.y - 1);
+    return face_offset + offset + offset_size + basis[0]*uv.x*face_data.size.x + basis[1]*uv.y*face_data.size.y;
+}
+
+// // /////////////// // //
+// // Shape Face Data // //
+// // /////////////// // //
+
+struct ShapeFaceData {
+    pos_block: vec3<i32>,
+    face:      u32,
+    box_min:   vec3<i32>,
+    box_max:   vec3<i32>,
+    back:      bool,
+}
+
+// Quad corner and edges in half voxels
+struct ShapeFaceQuad {
+    base:     vec3<i32>,
+    u:        vec3<i32>,
+    v:        vec3<i32>,
+    normal:   vec3<f32>,
+    positive: bool,
+}
+
+fn decode_shape_face_data(face_index: u32) -> ShapeFaceData {
+    let face = shape_faces[face_index];
+
+    let mask = get_coord_mask();
+    let base = 3u*coord_bits;
+
+    var result: ShapeFaceData;
+    result.pos_block = vec3<i32>(
+        i32( face                     & mask),
+        i32((face >>     coord_bits)  & mask),
+        i32((face >> (2u*coord_bits)) & mask)
+    );
+    result.face = (face >> base) & 0x07;
+    result.box_min = vec3<i32>(
+        i32((face >> (base + 3u)) & 0x01),
+        i32((face >> (base + 4u)) & 0x01),
+        i32((face >> (base + 5u)) & 0x01)
+    );
+    result.box_max = vec3<i32>(
+        i32((face >> (base + 6u)) & 0x01) + 1,
+        i32((face >> (base + 7u)) & 0x01) + 1,
+        i32((face >> (base + 8u)) & 0x01) + 1
+    );
+    result.back = ((face >> (base + 9u)) & 0x01) != 0;
+
+    return result;
+}
+
+fn get_shape_face_quad(shape_data: ShapeFaceData) -> ShapeFaceQuad {
+    let size = shape_data.box_max - shape_data.box_min;
+
+    var result: ShapeFaceQuad;
+    if (shape_data.face < 6) {
+        let basis = get_voxel_face_basis(shape_data.face);
+        result.base = abs(basis[0])*dot(get_shape_box_start(shape_data, basis[0]), abs(basis[0]))
+                    + abs(basis[1])*dot(get_shape_box_start(shape_data, basis[1]), abs(basis[1]))
+                    + abs(basis[2])*dot(get_shape_box_start(shape_data, basis[2]), abs(basis[2]));
+        result.u = basis[0]*size;
+        result.v = basis[1]*size;
+        result.normal = vec3<f32>(basis[2]);
+        result.positive = shape_data.face < 3;
+    } else {
+        let diagonal = shape_data.face == 6;
+        result.base = select(vec3<i32>(shape_data.box_min.xy, shape_data.box_max.z), shape_data.box_min, diagonal);
+        result.u = vec3<i32>(size.x, 0, select(-size.z, size.z, diagonal));
+        result.v = vec3<i32>(0, size.y, 0);
+        result.normal = cross(vec3<f32>(result.v), vec3<f32>(result.u));
+        result.positive = !shape_data.back;
+    }
+
+    return result;
+}
+
+// Axes running in the negative direction start from the far side of the box
+fn get_shape_box_start(shape_data: ShapeFaceData, axis: vec3<i32>) -> vec3<i32> {
+    return select(shape_data.box_min, shape_data.box_max, axis.x + axis.y + axis.z < 0);
+}
+
+fn get_shape_face_uv(quad: ShapeFaceQuad, vert_index: u32) -> vec2<i32> {
+    var uvs_positive = array<vec2<i32>, 6>(
+        vec2<i32>(0, 0),
+        vec2<i32>(0, 1),
+        vec2<i32>(1, 0),
+        vec2<i32>(1, 1),
+        vec2<i32>(1, 0),
+        vec2<i32>(0, 1),
+    );
+
+    var uvs_negative = array<vec2<i32>, 6>(
+        vec2<i32>(0, 0),
+        vec2<i32>(1, 0),
+        vec2<i32>(0, 1),
+        vec2<i32>(1, 1),
+        vec2<i32>(0, 1),
+        vec2<i32>(1, 0),
+    );
+
+    return select(uvs_negative[vert_index], uvs_positive[vert_index], quad.positive);
+}
+
+fn get_tile_colour(tile: u32) -> vec3<f32> {
+    // Spread tile identifiers over distinct tints until tiles have textures
+    let hash = tile * 0x9E3779B9u;
+    return vec3<f32>(
+        0.5 + f32((hash >> 24) & 0xFF)/510.0,
+        0.5 + f32((hash >> 16) & 0xFF)/510.0,
+        0.5 + f32((hash >>  8) & 0xFF)/510.0
+    );
 }
 
 fn component_max_3(a: vec3<i32>, b: i32) -> vec3<i32> {
@@ -3,43 +3,37 @@
 use std::{fs::File, io::BufWriter};
 
 use image::RgbImage;
-use nvm_v3d::{lighting::LightStorageWorld, meshing::{mesh_chunk_plane, VisFace}, tiles::TileIdentifier, world::{ChunkStorage, PosBlock, PosChunk}};
+use nvm_v3d::{lighting::LightStorageWorld, meshing::{mesh_chunk_shapes, validate_mesh, ChunkMesh, FaceOcclusionProvider, FaceTileProvider, FaceVisibilityProvider}, tiles::{TileIdentifier, TileRegistry}, world::{PosBlock, PosChunk, PosWorld, World}};
 
-pub fn read_vox(path: &str) -> ChunkStorage {
-    let mut storage = ChunkStorage::new_empty();
+pub fn read_vox(path: &str, id: TileIdentifier, registry: &TileRegistry) -> World {
+    let mut world = World::default();
     let data = &dot_vox::load(path).unwrap().models[0].voxels;
     for entry in data {
         if entry.x >= 32 || entry.y >= 32 || entry.z >= 32 { continue; }
-        storage.update(
-            PosBlock::new(entry.x as i16, entry.y as i16, entry.z as i16), 
-            TileIdentifier::ONE, 
-            true
+        world.set(
+            PosWorld::new(entry.x as i16, entry.y as i16, entry.z as i16), 
+            id, 
+            registry
         );
     }
-    storage
+    world
 }
 
-pub fn mesh_chunk(storage: &ChunkStorage) -> Vec<u32> {
-    let mut result = vec![0; 32*32*32*6];
-    let mut size = 0;
-    for x in 0..4 {
-        for y in 0..4 {
-            for z in 0..4 {
-                let offset = [x, y, z];
-                for i in 0..8 {
-                    size += mesh_chunk_plane(storage, offset, VisFace::PosX, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::PosY, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::PosZ, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::NegX, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::NegY, i, &mut result[size..]);
-                    size += mesh_chunk_plane(storage, offset, VisFace::NegZ, i, &mut result[size..]);
-                }
-                
-            }
-        }
-    }
-    result.truncate(size);
-    result
+/// Meshes the chunk with ambient occlusion, returning the faces and the raw
+/// tile of each face.
+pub fn mesh_chunk<T: FaceVisibilityProvider + FaceTileProvider + FaceOcclusionProvider + Sync>(storage: &T) -> (Vec<u32>, Vec<u32>) {
+    let mut mesh = ChunkMesh::default();
+    mesh.par_build_ao(storage);
+    debug_assert_eq!(validate_mesh(mesh.faces(), storage), Ok(()));
+    (mesh.faces().to_vec(), mesh.tiles().iter().map(|v| u32::from(v.to_raw())).collect())
+}
+
+/// Meshes the shaped voxels of the chunk, returning the shape faces and the
+/// raw tile of each face.
+pub fn mesh_shapes<T: FaceVisibilityProvider + FaceTileProvider + FaceOcclusionProvider>(storage: &T, registry: &TileRegistry) -> (Vec<u32>, Vec<u32>) {
+    let (mut faces, mut tiles) = (Vec::new(), Vec::new());
+    mesh_chunk_shapes(storage, registry, &mut faces, &mut tiles);
+    (faces, tiles.iter().map(|v| u32::from(v.to_raw())).collect())
 }
 
 pub fn write_lighting_data_to_image(path: &str, light_data: &LightStorageWorld, chunk: PosChunk, factor: u8) {
@@ -48,7 +42,7 @@ pub fn write_lighting_data_to_image(path: &str, light_data: &LightStorageWorld,
     for x in 0..32 {
         for y in 0..32 {
             for z in 0..32 {
-                let idx = PosBlock::new(x as i16, y as i16, z as i16).to_idx();
+                let idx = <PosBlock>::new(x as i16, y as i16, z as i16).to_idx();
                 image.put_pixel(x, y + z*32, image::Rgb([
                     chunk.get_channel(idx, 0)*factor,
                     chunk.get_channel(idx, 1)*factor,